@@ -0,0 +1,320 @@
+use std::{sync::Mutex, time::Duration};
+
+use tokio::{
+    sync::{Semaphore, SemaphorePermit},
+    time::Instant,
+};
+
+/// Configures an adaptive limit on the number of `list_with_delimiter` requests in flight.
+/// Set [`ListOptions::adaptive_concurrency`](crate::ListOptions::adaptive_concurrency) to
+/// enable it.
+///
+/// The limit is adjusted using AIMD (additive increase, multiplicative decrease), like TCP
+/// congestion control:
+/// - Each time `limit` consecutive requests succeed within `latency_target`, the limit
+///   increases by one (up to `max`).
+/// - When a request fails with a throttling error (as decided by `is_throttling`), the
+///   limit is multiplied by `decrease_factor` (down to `min`). Requests which were
+///   already in flight when the limit was cut don't cut it again.
+///
+/// This can be combined with [`ListOptions::max_concurrency`](crate::ListOptions::max_concurrency),
+/// which is then a hard upper limit.
+#[derive(Debug, Clone)]
+pub struct AdaptiveConcurrency {
+    /// The limit at the start of the traversal.
+    pub initial: usize,
+    /// The limit never drops below this. Values below 1 are treated as 1.
+    pub min: usize,
+    /// The limit never rises above this.
+    pub max: usize,
+    /// Only requests which succeed within this duration count towards increasing the limit.
+    pub latency_target: Duration,
+    /// The limit is multiplied by this after a throttling error. Should be in `(0, 1)`.
+    pub decrease_factor: f64,
+    /// Returns `true` if an error means the object store is overloaded. Defaults to
+    /// [`is_throttling`].
+    pub is_throttling: fn(&object_store::Error) -> bool,
+}
+
+impl Default for AdaptiveConcurrency {
+    fn default() -> Self {
+        Self {
+            initial: 16,
+            min: 1,
+            max: 1024,
+            latency_target: Duration::from_secs(2),
+            decrease_factor: 0.5,
+            is_throttling,
+        }
+    }
+}
+
+/// The default classifier for [`AdaptiveConcurrency::is_throttling`].
+///
+/// `object_store` doesn't expose HTTP status codes, so this looks for the tell-tale
+/// signs of throttling and timeouts in the message of an
+/// [`object_store::Error::Generic`], such as S3's `SlowDown`, `503`, `429` and
+/// `timed out`. (Requests which exceed
+/// [`ListOptions::request_timeout`](crate::ListOptions::request_timeout) also fail with a
+/// `Generic` error.) Every other variant, such as `NotFound`, returns `false`: their
+/// messages include the path, which could contain `429` or `503` by coincidence.
+pub fn is_throttling(e: &object_store::Error) -> bool {
+    const NEEDLES: [&str; 8] = [
+        "slowdown",
+        "slow down",
+        "throttl",
+        "too many requests",
+        "429",
+        "503",
+        "timed out",
+        "timeout",
+    ];
+    let object_store::Error::Generic { source, .. } = e else {
+        return false;
+    };
+    let msg = source.to_string().to_lowercase();
+    NEEDLES.iter().any(|needle| msg.contains(needle))
+}
+
+/// Implements [`AdaptiveConcurrency`] on top of a [`Semaphore`]. The number of permits
+/// is the limit, minus any `debt`.
+#[derive(Debug)]
+pub(crate) struct AdaptiveLimiter {
+    config: AdaptiveConcurrency,
+    semaphore: Semaphore,
+    state: Mutex<LimiterState>,
+}
+
+#[derive(Debug)]
+struct LimiterState {
+    limit: usize,
+    /// The number of permits which must be forgotten (instead of being returned to the
+    /// semaphore) because the limit was cut while they were in use.
+    debt: usize,
+    /// The number of fast successes since the limit last changed.
+    successes: usize,
+    /// Incremented every time the limit is cut.
+    generation: u64,
+}
+
+impl AdaptiveLimiter {
+    pub(crate) fn new(config: AdaptiveConcurrency) -> Self {
+        let min = config.min.max(1);
+        let limit = config.initial.clamp(min, config.max.max(min));
+        Self {
+            config,
+            semaphore: Semaphore::new(limit),
+            state: Mutex::new(LimiterState {
+                limit,
+                debt: 0,
+                successes: 0,
+                generation: 0,
+            }),
+        }
+    }
+
+    /// Waits until a request is allowed.
+    pub(crate) async fn acquire(&self) -> AdaptivePermit<'_> {
+        let permit = self
+            .semaphore
+            .acquire()
+            .await
+            .expect("the semaphore is never closed");
+        let generation = self.state.lock().unwrap().generation;
+        AdaptivePermit {
+            limiter: self,
+            permit: Some(permit),
+            generation,
+            start: Instant::now(),
+        }
+    }
+
+    /// The current limit.
+    #[cfg(test)]
+    pub(crate) fn limit(&self) -> usize {
+        self.state.lock().unwrap().limit
+    }
+
+    fn on_success(&self, latency: Duration) {
+        if latency > self.config.latency_target {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.successes += 1;
+        if state.successes >= state.limit && state.limit < self.config.max {
+            state.successes = 0;
+            state.limit += 1;
+            if state.debt > 0 {
+                state.debt -= 1;
+            } else {
+                self.semaphore.add_permits(1);
+            }
+        }
+    }
+
+    fn on_throttled(&self, generation: u64) {
+        let mut state = self.state.lock().unwrap();
+        if generation != state.generation {
+            // The limit has already been cut since this request started.
+            return;
+        }
+        let min = self.config.min.max(1);
+        let new_limit = ((state.limit as f64 * self.config.decrease_factor) as usize).max(min);
+        state.generation += 1;
+        state.successes = 0;
+        for _ in new_limit..state.limit {
+            match self.semaphore.try_acquire() {
+                Ok(permit) => permit.forget(),
+                Err(_) => state.debt += 1,
+            }
+        }
+        state.limit = new_limit.min(state.limit);
+    }
+}
+
+/// Permission to make one request, from [`AdaptiveLimiter::acquire`].
+pub(crate) struct AdaptivePermit<'a> {
+    limiter: &'a AdaptiveLimiter,
+    permit: Option<SemaphorePermit<'a>>,
+    generation: u64,
+    start: Instant,
+}
+
+impl AdaptivePermit<'_> {
+    /// Measures the latency of the request from now, rather than from when the permit was
+    /// acquired.
+    pub(crate) fn restart_clock(&mut self) {
+        self.start = Instant::now();
+    }
+
+    /// Feeds the outcome of the request back into the limiter.
+    pub(crate) fn finish<T>(self, result: &object_store::Result<T>) {
+        match result {
+            Ok(_) => self.limiter.on_success(self.start.elapsed()),
+            Err(e) if (self.limiter.config.is_throttling)(e) => {
+                self.limiter.on_throttled(self.generation)
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+impl Drop for AdaptivePermit<'_> {
+    fn drop(&mut self) {
+        let mut state = self.limiter.state.lock().unwrap();
+        if state.debt > 0 {
+            state.debt -= 1;
+            if let Some(permit) = self.permit.take() {
+                permit.forget();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn throttling_error() -> object_store::Result<()> {
+        Err(object_store::Error::Generic {
+            store: "S3",
+            source: "SlowDown: Please reduce your request rate".into(),
+        })
+    }
+
+    fn limiter(initial: usize) -> AdaptiveLimiter {
+        AdaptiveLimiter::new(AdaptiveConcurrency {
+            initial,
+            min: 2,
+            max: 10,
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_additive_increase() {
+        let limiter = limiter(4);
+        for _ in 0..4 {
+            limiter.acquire().await.finish(&Ok(()));
+        }
+        assert_eq!(limiter.limit(), 5);
+        assert_eq!(limiter.semaphore.available_permits(), 5);
+        for _ in 0..100 {
+            limiter.acquire().await.finish(&Ok(()));
+        }
+        assert_eq!(limiter.limit(), 10);
+        assert_eq!(limiter.semaphore.available_permits(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_multiplicative_decrease() {
+        let limiter = limiter(8);
+        limiter.acquire().await.finish(&throttling_error());
+        assert_eq!(limiter.limit(), 4);
+        assert_eq!(limiter.semaphore.available_permits(), 4);
+        limiter.acquire().await.finish(&throttling_error());
+        assert_eq!(limiter.limit(), 2);
+        limiter.acquire().await.finish(&throttling_error());
+        assert_eq!(limiter.limit(), 2);
+        assert_eq!(limiter.semaphore.available_permits(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_decrease_while_permits_are_in_use() {
+        let limiter = limiter(8);
+        let mut permits = vec![];
+        for _ in 0..8 {
+            permits.push(limiter.acquire().await);
+        }
+        // All eight requests are throttled, but only the first one cuts the limit.
+        for permit in permits {
+            permit.finish(&throttling_error());
+        }
+        assert_eq!(limiter.limit(), 4);
+        assert_eq!(limiter.semaphore.available_permits(), 4);
+    }
+
+    #[test]
+    fn test_is_throttling() {
+        assert!(is_throttling(&throttling_error().unwrap_err()));
+        let timeout = object_store::Error::Generic {
+            store: "list_with_depth",
+            source: "list_with_delimiter timed out after 1s".into(),
+        };
+        assert!(is_throttling(&timeout));
+        // Paths which happen to contain a status code aren't throttling.
+        let not_found = object_store::Error::NotFound {
+            path: "data/14290.nc".to_string(),
+            source: "not found".into(),
+        };
+        assert!(!is_throttling(&not_found));
+        let permission_denied = object_store::Error::PermissionDenied {
+            path: "runs/503/output".to_string(),
+            source: "access denied".into(),
+        };
+        assert!(!is_throttling(&permission_denied));
+    }
+
+    #[tokio::test]
+    async fn test_other_errors_and_slow_requests_are_ignored() {
+        let limiter = limiter(2);
+        let not_found: object_store::Result<()> = Err(object_store::Error::NotFound {
+            path: "foo".to_string(),
+            source: "not found".into(),
+        });
+        limiter.acquire().await.finish(&not_found);
+        assert_eq!(limiter.limit(), 2);
+
+        let limiter = AdaptiveLimiter::new(AdaptiveConcurrency {
+            initial: 2,
+            latency_target: Duration::ZERO,
+            ..Default::default()
+        });
+        for _ in 0..10 {
+            let permit = limiter.acquire().await;
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            permit.finish(&Ok(()));
+        }
+        assert_eq!(limiter.limit(), 2);
+    }
+}
@@ -0,0 +1,118 @@
+use std::sync::Arc;
+
+use arrow_array::{
+    builder::{StringBuilder, TimestampMicrosecondBuilder, UInt64Builder},
+    ArrayRef, RecordBatch, UInt64Array,
+};
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef, TimeUnit};
+use object_store::ObjectMeta;
+
+/// The schema of the [`RecordBatch`]es from [`objects_to_record_batch`]:
+///
+/// | Column          | Arrow type                    | Nullable | Meaning                                             |
+/// |-----------------|-------------------------------|----------|-----------------------------------------------------|
+/// | `location`      | `Utf8`                        | no       | The full path of the object.                        |
+/// | `size`          | `UInt64`                      | no       | The size of the object, in bytes.                   |
+/// | `last_modified` | `Timestamp(Microsecond, UTC)` | no       | When the object was last modified.                  |
+/// | `e_tag`         | `Utf8`                        | yes      | The object's ETag, if the store returned one.       |
+/// | `version`       | `Utf8`                        | yes      | The object's version, if the store is versioned.    |
+/// | `depth`         | `UInt64`                      | no       | The depth of the listing which returned the object. |
+///
+/// The schema is stable: columns may be added at the end, but existing columns won't be
+/// renamed, removed, reordered or change type.
+pub fn objects_schema() -> SchemaRef {
+    let timestamp = DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()));
+    Arc::new(Schema::new(vec![
+        Field::new("location", DataType::Utf8, false),
+        Field::new("size", DataType::UInt64, false),
+        Field::new("last_modified", timestamp, false),
+        Field::new("e_tag", DataType::Utf8, true),
+        Field::new("version", DataType::Utf8, true),
+        Field::new("depth", DataType::UInt64, false),
+    ]))
+}
+
+/// Converts `objects`, returned by a listing at `depth`, into a [`RecordBatch`] with one
+/// row per object, in the schema given by [`objects_schema`]. `last_modified` is
+/// truncated to microseconds.
+///
+/// To convert a listing which is too big to hold in memory, call this for each
+/// [`PrefixListing`](crate::PrefixListing) from
+/// [`list_with_depth_stream`](crate::list_with_depth_stream).
+pub fn objects_to_record_batch(
+    objects: &[ObjectMeta],
+    depth: usize,
+) -> Result<RecordBatch, ArrowError> {
+    let n = objects.len();
+    let mut locations = StringBuilder::with_capacity(n, n * 64);
+    let mut sizes = UInt64Builder::with_capacity(n);
+    let mut last_modified = TimestampMicrosecondBuilder::with_capacity(n).with_timezone("UTC");
+    let mut e_tags = StringBuilder::new();
+    let mut versions = StringBuilder::new();
+    for object_meta in objects {
+        locations.append_value(&object_meta.location);
+        sizes.append_value(object_meta.size as u64);
+        last_modified.append_value(object_meta.last_modified.timestamp_micros());
+        e_tags.append_option(object_meta.e_tag.as_deref());
+        versions.append_option(object_meta.version.as_deref());
+    }
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(locations.finish()),
+        Arc::new(sizes.finish()),
+        Arc::new(last_modified.finish()),
+        Arc::new(e_tags.finish()),
+        Arc::new(versions.finish()),
+        Arc::new(UInt64Array::from_value(depth as u64, n)),
+    ];
+    RecordBatch::try_new(objects_schema(), columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{
+        cast::AsArray,
+        types::{TimestampMicrosecondType, UInt64Type},
+        Array,
+    };
+    use object_store::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn test_objects_to_record_batch() {
+        let objects = vec![
+            ObjectMeta {
+                location: Path::from("foo/a.txt"),
+                last_modified: "2023-11-14T22:13:20Z".parse().unwrap(),
+                size: 42,
+                e_tag: Some("abc".to_string()),
+                version: None,
+            },
+            ObjectMeta {
+                location: Path::from("foo/b.txt"),
+                last_modified: "2023-11-14T22:13:21Z".parse().unwrap(),
+                size: 7,
+                e_tag: None,
+                version: Some("2".to_string()),
+            },
+        ];
+        let batch = objects_to_record_batch(&objects, 1).unwrap();
+        assert_eq!(batch.schema(), objects_schema());
+        assert_eq!(batch.num_rows(), 2);
+
+        let locations = batch.column(0).as_string::<i32>();
+        assert_eq!(locations.value(1), "foo/b.txt");
+        let sizes = batch.column(1).as_primitive::<UInt64Type>();
+        assert_eq!(sizes.values(), &[42, 7]);
+        let last_modified = batch.column(2).as_primitive::<TimestampMicrosecondType>();
+        assert_eq!(last_modified.value(0), 1_700_000_000_000_000);
+        let e_tags = batch.column(3).as_string::<i32>();
+        assert_eq!(e_tags.value(0), "abc");
+        assert!(e_tags.is_null(1));
+        let depths = batch.column(5).as_primitive::<UInt64Type>();
+        assert_eq!(depths.values(), &[1, 1]);
+
+        let empty = objects_to_record_batch(&[], 0).unwrap();
+        assert_eq!(empty.num_rows(), 0);
+    }
+}
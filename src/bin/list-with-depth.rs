@@ -0,0 +1,407 @@
+//! Lists the objects and common prefixes in an object store at a given depth.
+//!
+//! ```text
+//! list-with-depth s3://bucket/path --depth 2
+//! list-with-depth tree s3://bucket/path --depth 2 --sizes
+//! ```
+//!
+//! Credentials and other configuration are read from the store's environment variables
+//! (those starting with `AWS_`, `GOOGLE_` or `AZURE_`, such as `AWS_ACCESS_KEY_ID`), as
+//! understood by `object_store`, and from `--option key=value`.
+use std::{
+    ffi::OsString,
+    io::{self, BufWriter, Write},
+    process::ExitCode,
+    sync::Arc,
+};
+
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use futures::TryStreamExt;
+use list_with_depth::{
+    list_tree_opts, list_with_depth_opts, list_with_depth_stream_opts, write_json, write_ndjson,
+    write_tree, Charset, CsvColumn, CsvWriter, ListOptions, ListRecord, PrefixListing, TreeFormat,
+};
+use object_store::{path::Path, ListResult, ObjectStore, ObjectStoreScheme};
+use url::Url;
+
+/// List objects in an object store at a given depth.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// List the objects and common prefixes at the given depth. This is the default.
+    Ls(LsArgs),
+    /// Print every level down to the given depth as a tree.
+    Tree(TreeArgs),
+}
+
+/// The arguments shared by every subcommand.
+#[derive(Debug, Args)]
+struct StoreArgs {
+    /// The store to list, and optionally a prefix within it, e.g. `s3://bucket/path`,
+    /// `gs://bucket`, `az://container`, `file:///tmp/data` or `memory:///`.
+    url: Url,
+
+    /// A prefix to list, relative to the path in the URL.
+    #[arg(short, long)]
+    prefix: Option<String>,
+
+    /// The depth to list at. 0 lists the prefix itself, 1 lists each of its
+    /// sub-prefixes, and so on.
+    #[arg(short, long, default_value_t = 1)]
+    depth: usize,
+
+    /// The maximum number of list requests in flight at once.
+    #[arg(short = 'j', long)]
+    concurrency: Option<usize>,
+
+    /// Configuration for the object store, e.g. `-o aws_region=eu-west-1`. Overrides
+    /// environment variables.
+    #[arg(short = 'o', long = "option", value_name = "KEY=VALUE", value_parser = parse_key_value)]
+    options: Vec<(String, String)>,
+}
+
+#[derive(Debug, Args)]
+struct LsArgs {
+    #[command(flatten)]
+    store: StoreArgs,
+
+    /// How to print the results.
+    #[arg(short, long, value_enum, default_value_t = Format::Plain)]
+    format: Format,
+}
+
+#[derive(Debug, Args)]
+struct TreeArgs {
+    #[command(flatten)]
+    store: StoreArgs,
+
+    /// Show the size of each object, in bytes.
+    #[arg(short, long)]
+    sizes: bool,
+
+    /// Show when each object was last modified.
+    #[arg(short, long)]
+    modified: bool,
+
+    /// Don't show the number of objects and prefixes beneath each prefix.
+    #[arg(long)]
+    no_counts: bool,
+
+    /// Show at most this many entries beneath each prefix.
+    #[arg(short = 'n', long)]
+    max_entries: Option<usize>,
+
+    /// Draw the tree with ASCII characters rather than Unicode.
+    #[arg(long)]
+    ascii: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// One path per line. Common prefixes end with `/`.
+    Plain,
+    /// Like `aws s3 ls`: the size and modification time of each object, and `PRE` for
+    /// each common prefix.
+    Long,
+    /// A JSON array with one record per object or common prefix. See `ListRecord` in
+    /// the library's documentation for the schema.
+    Json,
+    /// Newline-delimited JSON: one record per line, in the same schema as `json`. Each
+    /// prefix is printed as soon as it's listed, so prefixes may be out of order.
+    Ndjson,
+    /// CSV, with a header row and one row per object or common prefix. Each prefix is
+    /// printed as soon as it's listed, so prefixes may be out of order.
+    Csv,
+}
+
+/// Inserts `ls` after the program name, unless the first argument is a subcommand or
+/// asks for help or the version, so that `list-with-depth s3://bucket` means
+/// `list-with-depth ls s3://bucket`.
+fn with_default_subcommand(mut args: Vec<OsString>) -> Vec<OsString> {
+    let needs_ls = args.get(1).is_some_and(|first| {
+        let first = first.to_string_lossy();
+        let is_subcommand = Cli::command()
+            .get_subcommands()
+            .any(|subcommand| subcommand.get_name() == first);
+        // Clap adds a `help` subcommand, which `get_subcommands` doesn't include.
+        let is_help = matches!(&*first, "help" | "-h" | "--help" | "-V" | "--version");
+        !is_subcommand && !is_help
+    });
+    if needs_ls {
+        args.insert(1, "ls".into());
+    }
+    args
+}
+
+fn parse_key_value(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, not {s:?}"))?;
+    Ok((key.to_ascii_lowercase(), value.to_string()))
+}
+
+impl StoreArgs {
+    fn list_options(&self) -> ListOptions {
+        ListOptions {
+            max_concurrency: self.concurrency,
+            ..Default::default()
+        }
+    }
+}
+
+/// Returns the environment variables which configure the store at `url`, with lowercased
+/// keys. Like `AmazonS3Builder::from_env` (and the other `from_env` builders), this only
+/// takes variables with the store's own prefix, such as `AWS_`. `object_store` also
+/// understands bare keys such as `token`, `endpoint` and `timeout`, so passing every
+/// variable would let unrelated ones change the configuration.
+fn store_env_vars(
+    url: &Url,
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> Vec<(String, String)> {
+    let env_prefix = match ObjectStoreScheme::parse(url) {
+        Ok((ObjectStoreScheme::AmazonS3, _)) => "AWS_",
+        Ok((ObjectStoreScheme::GoogleCloudStorage, _)) => "GOOGLE_",
+        Ok((ObjectStoreScheme::MicrosoftAzure, _)) => "AZURE_",
+        // The other stores aren't configured by environment variables.
+        _ => return vec![],
+    };
+    vars.into_iter()
+        .filter(|(key, _)| key.starts_with(env_prefix))
+        .map(|(key, value)| (key.to_ascii_lowercase(), value))
+        .collect()
+}
+
+/// Builds the store from the URL, and returns the store and the prefix to list.
+fn build_store(args: &StoreArgs) -> object_store::Result<(Arc<dyn ObjectStore>, Option<Path>)> {
+    let options = store_env_vars(&args.url, std::env::vars())
+        .into_iter()
+        .chain(args.options.iter().cloned());
+    let (store, url_path) = object_store::parse_url_opts(&args.url, options)?;
+    let prefix = join_prefix(&url_path, args.prefix.as_deref());
+    Ok((Arc::from(store), prefix))
+}
+
+/// Appends `prefix` to the path from the URL. Returns `None` for the root of the store.
+fn join_prefix(url_path: &Path, prefix: Option<&str>) -> Option<Path> {
+    let prefix = prefix.map(Path::from).unwrap_or_default();
+    let joined: Path = url_path.parts().chain(prefix.parts()).collect();
+    Some(joined).filter(|joined| !joined.as_ref().is_empty())
+}
+
+/// Writes one line per object and common prefix, like `ls`, or like `aws s3 ls` if `long`.
+fn write_plain(
+    out: &mut impl Write,
+    list_result: &ListResult,
+    depth: usize,
+    long: bool,
+) -> io::Result<()> {
+    for record in ListRecord::from_list_result(list_result, depth) {
+        match (record, long) {
+            (ListRecord::Prefix { location, .. }, true) => {
+                writeln!(out, "{:>19} {:>12} {location}/", "", "PRE")?
+            }
+            (ListRecord::Prefix { location, .. }, false) => writeln!(out, "{location}/")?,
+            (
+                ListRecord::Object {
+                    location,
+                    size,
+                    last_modified,
+                    ..
+                },
+                true,
+            ) => writeln!(
+                out,
+                "{} {size:>12} {location}",
+                last_modified.format("%Y-%m-%d %H:%M:%S")
+            )?,
+            (ListRecord::Object { location, .. }, false) => writeln!(out, "{location}")?,
+        }
+    }
+    Ok(())
+}
+
+/// Any error which ends the program.
+type BoxError = Box<dyn std::error::Error>;
+
+/// Plain, long and JSON output need the whole listing, sorted. NDJSON and CSV are written
+/// a prefix at a time as each listing arrives, so the whole listing never has to fit in
+/// memory: the rows are in order of path within each prefix, but the prefixes are in the
+/// order in which their listings finish.
+async fn ls(args: LsArgs) -> Result<(), BoxError> {
+    let (store, prefix) = build_store(&args.store)?;
+    let options = args.store.list_options();
+    let depth = args.store.depth;
+    let mut out = BufWriter::new(io::stdout().lock());
+    match args.format {
+        Format::Plain | Format::Long => {
+            let list_result = list_with_depth_opts(store, prefix.as_ref(), depth, options).await?;
+            write_plain(&mut out, &list_result, depth, args.format == Format::Long)?;
+        }
+        Format::Json => {
+            let list_result = list_with_depth_opts(store, prefix.as_ref(), depth, options).await?;
+            write_json(&mut out, &list_result, depth)?;
+        }
+        Format::Ndjson => {
+            let mut stream = list_with_depth_stream_opts(store, prefix.as_ref(), depth, options);
+            while let Some(listing) = stream.try_next().await? {
+                let listing = sorted(listing);
+                write_ndjson(&mut out, &listing.list_result, listing.depth)?;
+            }
+        }
+        Format::Csv => {
+            let mut stream = list_with_depth_stream_opts(store, prefix.as_ref(), depth, options);
+            let mut writer = CsvWriter::new(&mut out, &CsvColumn::ALL)?;
+            while let Some(listing) = stream.try_next().await? {
+                writer.write_listing(&listing)?;
+            }
+            writer.flush()?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Sorts the objects and common prefixes in a single prefix's listing by path.
+fn sorted(mut listing: PrefixListing) -> PrefixListing {
+    let list_result = &mut listing.list_result;
+    list_result
+        .objects
+        .sort_unstable_by(|a, b| a.location.cmp(&b.location));
+    list_result.common_prefixes.sort_unstable();
+    listing
+}
+
+async fn tree(args: TreeArgs) -> Result<(), BoxError> {
+    let (store, prefix) = build_store(&args.store)?;
+    let options = args.store.list_options();
+    let tree = list_tree_opts(store, prefix.as_ref(), args.store.depth, options).await?;
+    let format = TreeFormat {
+        charset: if args.ascii {
+            Charset::Ascii
+        } else {
+            Charset::Unicode
+        },
+        sizes: args.sizes,
+        modified: args.modified,
+        counts: !args.no_counts,
+        max_entries: args.max_entries,
+    };
+    let mut out = BufWriter::new(io::stdout().lock());
+    write_tree(&mut out, &tree, &format)?;
+    out.flush()?;
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse_from(with_default_subcommand(std::env::args_os().collect()));
+    let result = match cli.command {
+        Command::Ls(args) => ls(args).await,
+        Command::Tree(args) => tree(args).await,
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        // E.g. when piped into `head`.
+        Err(e)
+            if e.downcast_ref::<io::Error>()
+                .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe) =>
+        {
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_args() {
+        Cli::command().debug_assert();
+        let parse = |args: &[&str]| {
+            let args = args.iter().map(OsString::from).collect();
+            Cli::try_parse_from(with_default_subcommand(args)).unwrap()
+        };
+
+        let cli = parse(&[
+            "list-with-depth",
+            "s3://bucket/path",
+            "-d",
+            "2",
+            "-o",
+            "AWS_REGION=eu-west-1",
+        ]);
+        let Command::Ls(args) = cli.command else {
+            panic!("expected the default ls subcommand");
+        };
+        assert_eq!(args.store.depth, 2);
+        assert_eq!(
+            args.store.options,
+            vec![("aws_region".to_string(), "eu-west-1".to_string())]
+        );
+
+        let cli = parse(&["list-with-depth", "tree", "memory:///", "--sizes"]);
+        let Command::Tree(args) = cli.command else {
+            panic!("expected the tree subcommand");
+        };
+        assert!(args.sizes);
+        assert_eq!(args.store.url.as_str(), "memory:///");
+
+        for help in [
+            &["list-with-depth", "help"][..],
+            &["list-with-depth", "help", "tree"],
+        ] {
+            let args = help.iter().map(OsString::from).collect();
+            let err = Cli::try_parse_from(with_default_subcommand(args)).unwrap_err();
+            assert_eq!(err.kind(), clap::error::ErrorKind::DisplayHelp, "{help:?}");
+        }
+    }
+
+    #[test]
+    fn test_store_env_vars() {
+        let vars = || {
+            [
+                ("AWS_REGION", "eu-west-1"),
+                ("GOOGLE_BUCKET", "bucket"),
+                ("TOKEN", "unrelated"),
+                ("TIMEOUT", "30"),
+            ]
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+        };
+        let s3 = Url::parse("s3://bucket/path").unwrap();
+        assert_eq!(
+            store_env_vars(&s3, vars()),
+            vec![("aws_region".to_string(), "eu-west-1".to_string())]
+        );
+        let gcs = Url::parse("gs://bucket").unwrap();
+        assert_eq!(
+            store_env_vars(&gcs, vars()),
+            vec![("google_bucket".to_string(), "bucket".to_string())]
+        );
+        let memory = Url::parse("memory:///").unwrap();
+        assert!(store_env_vars(&memory, vars()).is_empty());
+    }
+
+    #[test]
+    fn test_join_prefix() {
+        assert_eq!(join_prefix(&Path::default(), None), None);
+        assert_eq!(
+            join_prefix(&Path::from("a/b"), Some("c/d/")),
+            Some(Path::from("a/b/c/d"))
+        );
+        assert_eq!(
+            join_prefix(&Path::default(), Some("c")),
+            Some(Path::from("c"))
+        );
+    }
+}
@@ -0,0 +1,399 @@
+use std::collections::BTreeMap;
+#[cfg(feature = "serde")]
+use std::{borrow::Cow, path::PathBuf, time::Duration};
+
+use object_store::{path::Path, ListResult};
+
+use crate::{ListOptions, PrefixListing};
+
+/// The state of a [`list_with_depth`](crate::list_with_depth) traversal, from which it can
+/// be continued with [`resume_list_with_depth`](crate::resume_list_with_depth).
+///
+/// A checkpoint holds the results collected so far, plus the *frontier*: the prefixes which
+/// have been discovered but not yet listed. Resuming lists the frontier (and everything
+/// beneath it, down to `depth`), so no prefix is listed twice.
+///
+/// There are two ways to get a checkpoint part-way through a listing:
+/// - [`PartialListResult::into_checkpoint`](crate::PartialListResult::into_checkpoint),
+///   e.g. after the [deadline](ListOptions::deadline) has passed.
+/// - With the `serde` feature, set `ListOptions::checkpoint_file` to periodically write
+///   the checkpoint to a local file, and `Checkpoint::load` it after a crash.
+#[derive(Debug)]
+pub struct Checkpoint {
+    /// The prefix that the listing started from.
+    pub prefix: Option<Path>,
+    /// The depth being listed.
+    pub depth: usize,
+    /// The prefixes which still need to be listed, and their depths.
+    pub pending: BTreeMap<Path, usize>,
+    /// The objects and common prefixes found at `depth` so far.
+    pub list_result: ListResult,
+}
+
+impl Checkpoint {
+    /// Creates a checkpoint for a listing which hasn't started yet.
+    pub fn new(prefix: Option<&Path>, depth: usize) -> Self {
+        Self {
+            prefix: prefix.cloned(),
+            depth,
+            pending: BTreeMap::from([(prefix.cloned().unwrap_or_default(), 0)]),
+            list_result: ListResult {
+                objects: vec![],
+                common_prefixes: vec![],
+            },
+        }
+    }
+
+    /// Returns `true` if there's nothing left to list.
+    pub fn is_complete(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Records a listing from a traversal which emits every depth from `0` to `self.depth`.
+    /// Parents are always emitted before their children, so the children of `prefix`
+    /// become pending just before `prefix` itself stops being pending.
+    pub(crate) fn record(&mut self, prefix_listing: PrefixListing) {
+        let PrefixListing {
+            prefix,
+            depth,
+            list_result,
+        } = prefix_listing;
+        if depth < self.depth {
+            self.pending.extend(
+                list_result
+                    .common_prefixes
+                    .into_iter()
+                    .map(|common_prefix| (common_prefix, depth + 1)),
+            );
+        } else {
+            self.list_result.objects.extend(list_result.objects);
+            self.list_result
+                .common_prefixes
+                .extend(list_result.common_prefixes);
+        }
+        self.pending.remove(&prefix);
+    }
+
+    /// The pending prefixes, with their depths and parents, ready for
+    /// [`spawn_traversal_from`](crate::traverse::spawn_traversal_from).
+    pub(crate) fn frontier(&self) -> Vec<(Path, usize, Vec<Path>)> {
+        let n_parts_of_prefix = self.prefix.as_ref().map_or(0, |p| p.parts().count());
+        self.pending
+            .iter()
+            .map(|(path, &depth)| {
+                // Every level of the traversal adds one part to the path, so the parents
+                // are the path truncated to each of the levels above it.
+                let parents = (n_parts_of_prefix..n_parts_of_prefix + depth)
+                    .filter(|&n_parts| n_parts > 0)
+                    .map(|n_parts| path.parts().take(n_parts).collect())
+                    .collect();
+                (path.clone(), depth, parents)
+            })
+            .collect()
+    }
+}
+
+/// Where and how often to write checkpoints. See [`ListOptions::checkpoint_file`].
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone)]
+pub struct CheckpointFile {
+    /// The local file to write the checkpoint to. It's replaced atomically (by writing to
+    /// a temporary file in the same directory and then renaming it), so a crash part-way
+    /// through a write won't corrupt it.
+    pub path: PathBuf,
+    /// The minimum time between writes.
+    pub interval: Duration,
+}
+
+#[cfg(feature = "serde")]
+impl CheckpointFile {
+    /// Writes checkpoints to `path`, at most once a minute.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            interval: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Writes a [`Checkpoint`] to `ListOptions::checkpoint_file` (if set) every so often.
+#[cfg(feature = "serde")]
+pub(crate) struct Autosave {
+    checkpoint_file: Option<CheckpointFile>,
+    last_save: tokio::time::Instant,
+}
+
+#[cfg(feature = "serde")]
+impl Autosave {
+    pub(crate) fn new(options: &ListOptions) -> Self {
+        Self {
+            checkpoint_file: options.checkpoint_file.clone(),
+            last_save: tokio::time::Instant::now(),
+        }
+    }
+
+    /// Saves `checkpoint` if the interval has passed since the last save.
+    pub(crate) async fn tick(&mut self, checkpoint: &mut Checkpoint) -> std::io::Result<()> {
+        match &self.checkpoint_file {
+            Some(checkpoint_file) if self.last_save.elapsed() >= checkpoint_file.interval => {
+                self.save(checkpoint).await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Saves `checkpoint` now.
+    ///
+    /// Serializing a checkpoint with millions of objects, and writing it to disk, takes a
+    /// while, so it's done on a blocking thread rather than stalling the runtime. The
+    /// checkpoint is moved to that thread and back, rather than cloned.
+    pub(crate) async fn save(&mut self, checkpoint: &mut Checkpoint) -> std::io::Result<()> {
+        if let Some(checkpoint_file) = &self.checkpoint_file {
+            let path = checkpoint_file.path.clone();
+            let owned = std::mem::replace(checkpoint, Checkpoint::new(None, 0));
+            let (owned, result) = tokio::task::spawn_blocking(move || {
+                let result = owned.save(path);
+                (owned, result)
+            })
+            .await
+            .map_err(|e| match e.try_into_panic() {
+                Ok(panic) => std::panic::resume_unwind(panic),
+                // The runtime is shutting down. The checkpoint went with the task, but the
+                // error means the caller gives up on the listing anyway.
+                Err(e) => std::io::Error::other(e),
+            })?;
+            *checkpoint = owned;
+            result?;
+            self.last_save = tokio::time::Instant::now();
+        }
+        Ok(())
+    }
+}
+
+/// Without the `serde` feature, checkpoints can't be written, so this does nothing.
+#[cfg(not(feature = "serde"))]
+pub(crate) struct Autosave;
+
+#[cfg(not(feature = "serde"))]
+impl Autosave {
+    pub(crate) fn new(_options: &ListOptions) -> Self {
+        Self
+    }
+
+    pub(crate) async fn tick(&mut self, _checkpoint: &mut Checkpoint) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    pub(crate) async fn save(&mut self, _checkpoint: &mut Checkpoint) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Checkpoint {
+    /// Writes the checkpoint to a local file as JSON, atomically replacing the file if it
+    /// already exists.
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        let path = path.as_ref();
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(&tmp_path)?);
+        serde_json::to_writer(&mut writer, self)?;
+        std::io::Write::flush(&mut writer)?;
+        std::fs::rename(tmp_path, path)
+    }
+
+    /// Reads a checkpoint written by [`Checkpoint::save`].
+    pub fn load(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        let reader = std::io::BufReader::new(std::fs::File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+}
+
+/// The serialized form of a [`Checkpoint`]. `Path` and `ObjectMeta` don't implement serde's
+/// traits, so they're converted to and from these records.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CheckpointRecord<'a> {
+    /// Incremented whenever the format changes incompatibly.
+    version: u32,
+    prefix: Option<Cow<'a, str>>,
+    depth: usize,
+    pending: Vec<PendingRecord<'a>>,
+    objects: Vec<ObjectRecord<'a>>,
+    common_prefixes: Vec<Cow<'a, str>>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct PendingRecord<'a> {
+    prefix: Cow<'a, str>,
+    depth: usize,
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ObjectRecord<'a> {
+    location: Cow<'a, str>,
+    last_modified: chrono::DateTime<chrono::Utc>,
+    size: usize,
+    e_tag: Option<Cow<'a, str>>,
+    version: Option<Cow<'a, str>>,
+}
+
+#[cfg(feature = "serde")]
+const CHECKPOINT_VERSION: u32 = 1;
+
+#[cfg(feature = "serde")]
+fn borrow(path: &Path) -> Cow<'_, str> {
+    Cow::Borrowed(path.as_ref())
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Checkpoint {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        CheckpointRecord {
+            version: CHECKPOINT_VERSION,
+            prefix: self.prefix.as_ref().map(borrow),
+            depth: self.depth,
+            pending: self
+                .pending
+                .iter()
+                .map(|(prefix, &depth)| PendingRecord {
+                    prefix: borrow(prefix),
+                    depth,
+                })
+                .collect(),
+            objects: self
+                .list_result
+                .objects
+                .iter()
+                .map(|object_meta| ObjectRecord {
+                    location: borrow(&object_meta.location),
+                    last_modified: object_meta.last_modified,
+                    size: object_meta.size,
+                    e_tag: object_meta.e_tag.as_deref().map(Cow::Borrowed),
+                    version: object_meta.version.as_deref().map(Cow::Borrowed),
+                })
+                .collect(),
+            common_prefixes: self
+                .list_result
+                .common_prefixes
+                .iter()
+                .map(borrow)
+                .collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Checkpoint {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error as _;
+        let record = CheckpointRecord::deserialize(deserializer)?;
+        if record.version != CHECKPOINT_VERSION {
+            return Err(D::Error::custom(format!(
+                "unsupported checkpoint version {} (expected {CHECKPOINT_VERSION})",
+                record.version
+            )));
+        }
+        let parse = |path: Cow<'_, str>| Path::parse(path).map_err(D::Error::custom);
+        let mut pending = BTreeMap::new();
+        for PendingRecord { prefix, depth } in record.pending {
+            pending.insert(parse(prefix)?, depth);
+        }
+        let mut objects = Vec::with_capacity(record.objects.len());
+        for object in record.objects {
+            objects.push(object_store::ObjectMeta {
+                location: parse(object.location)?,
+                last_modified: object.last_modified,
+                size: object.size,
+                e_tag: object.e_tag.map(Cow::into_owned),
+                version: object.version.map(Cow::into_owned),
+            });
+        }
+        Ok(Self {
+            prefix: record.prefix.map(parse).transpose()?,
+            depth: record.depth,
+            pending,
+            list_result: ListResult {
+                objects,
+                common_prefixes: record
+                    .common_prefixes
+                    .into_iter()
+                    .map(parse)
+                    .collect::<Result<_, _>>()?,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frontier_parents() {
+        let mut checkpoint = Checkpoint::new(Some(&Path::from("a")), 3);
+        checkpoint.pending.clear();
+        checkpoint.pending.insert(Path::from("a/b/c"), 2);
+        assert_eq!(
+            checkpoint.frontier(),
+            vec![(
+                Path::from("a/b/c"),
+                2,
+                vec![Path::from("a"), Path::from("a/b")]
+            )]
+        );
+
+        // The root of the store isn't a parent.
+        let mut checkpoint = Checkpoint::new(None, 3);
+        checkpoint.pending.insert(Path::from("a/b"), 2);
+        assert_eq!(
+            checkpoint.frontier(),
+            vec![
+                (Path::default(), 0, vec![]),
+                (Path::from("a/b"), 2, vec![Path::from("a")])
+            ]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let mut checkpoint = Checkpoint::new(Some(&Path::from("foo")), 2);
+        checkpoint.pending.insert(Path::from("foo/baz"), 1);
+        checkpoint
+            .list_result
+            .objects
+            .push(object_store::ObjectMeta {
+                location: Path::from("foo/bar/c.txt"),
+                last_modified: chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+                size: 42,
+                e_tag: Some("etag".to_string()),
+                version: None,
+            });
+        checkpoint
+            .list_result
+            .common_prefixes
+            .push(Path::from("foo/bar/qux"));
+
+        let json = serde_json::to_string(&checkpoint).unwrap();
+        let round_tripped: Checkpoint = serde_json::from_str(&json).unwrap();
+        assert_eq!(round_tripped.prefix, checkpoint.prefix);
+        assert_eq!(round_tripped.depth, 2);
+        assert_eq!(round_tripped.pending, checkpoint.pending);
+        assert_eq!(
+            round_tripped.list_result.objects,
+            checkpoint.list_result.objects
+        );
+        assert_eq!(
+            round_tripped.list_result.common_prefixes,
+            checkpoint.list_result.common_prefixes
+        );
+
+        let json = json.replace("\"version\":1", "\"version\":999");
+        assert!(serde_json::from_str::<Checkpoint>(&json).is_err());
+    }
+}
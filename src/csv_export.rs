@@ -0,0 +1,263 @@
+use std::io::{self, Write};
+
+use chrono::SecondsFormat;
+use object_store::ListResult;
+
+use crate::{ListRecord, PrefixListing};
+
+/// A column written by [`CsvWriter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsvColumn {
+    /// The full path of the object. Common prefixes end with `/`.
+    Path,
+    /// The path of the prefix which contains the object or common prefix. Empty at the
+    /// root of the store.
+    ParentPrefix,
+    /// The depth of the listing which returned the object or common prefix.
+    Depth,
+    /// The size of the object, in bytes. Empty for common prefixes.
+    Size,
+    /// When the object was last modified, in RFC 3339 format, in UTC. Empty for common
+    /// prefixes.
+    LastModified,
+    /// The object's ETag. Empty for common prefixes, and if the store didn't return one.
+    ETag,
+}
+
+impl CsvColumn {
+    /// Every column, in the order in which they're usually written.
+    pub const ALL: [Self; 6] = [
+        Self::Path,
+        Self::ParentPrefix,
+        Self::Depth,
+        Self::Size,
+        Self::LastModified,
+        Self::ETag,
+    ];
+
+    /// The name of the column in the header row.
+    pub fn header(self) -> &'static str {
+        match self {
+            Self::Path => "path",
+            Self::ParentPrefix => "parent_prefix",
+            Self::Depth => "depth",
+            Self::Size => "size",
+            Self::LastModified => "last_modified",
+            Self::ETag => "e_tag",
+        }
+    }
+}
+
+/// Writes listings as CSV, with one row per object or common prefix.
+///
+/// Rows are written as each listing is passed in, so a listing of any size can be
+/// written from [`list_with_depth_stream`](crate::list_with_depth_stream) without holding
+/// it all in memory:
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use futures::TryStreamExt;
+/// # use list_with_depth::{list_with_depth_stream, CsvColumn, CsvWriter};
+/// # use object_store::{memory::InMemory, ObjectStore, PutPayload};
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let store = Arc::new(InMemory::new());
+/// # store.put(&"foo/a,b.txt".into(), PutPayload::new()).await?;
+/// let columns = [CsvColumn::Path, CsvColumn::Size];
+/// let mut writer = CsvWriter::new(vec![], &columns)?;
+/// let mut stream = list_with_depth_stream(store, None, 1);
+/// while let Some(listing) = stream.try_next().await? {
+///     writer.write_listing(&listing)?;
+/// }
+/// let csv = String::from_utf8(writer.into_inner()?)?;
+/// assert_eq!(csv, "path,size\n\"foo/a,b.txt\",0\n");
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Fields are quoted if they contain a comma, a quote or a newline.
+#[derive(Debug)]
+pub struct CsvWriter<W: Write> {
+    writer: csv::Writer<W>,
+    columns: Vec<CsvColumn>,
+}
+
+impl<W: Write> CsvWriter<W> {
+    /// Writes the header row for `columns` to `out`. `out` is buffered internally.
+    pub fn new(out: W, columns: &[CsvColumn]) -> io::Result<Self> {
+        let mut writer = csv::Writer::from_writer(out);
+        writer.write_record(columns.iter().map(|column| column.header()))?;
+        Ok(Self {
+            writer,
+            columns: columns.to_vec(),
+        })
+    }
+
+    /// Writes a row for each object and common prefix in `listing`, in order of path.
+    pub fn write_listing(&mut self, listing: &PrefixListing) -> io::Result<()> {
+        self.write_list_result(&listing.list_result, listing.depth)
+    }
+
+    /// Writes a row for each object and common prefix in `list_result`, in order of
+    /// path. `depth` is the depth at which `list_result` was listed.
+    ///
+    /// `list_result` doesn't need to be sorted: a single prefix's listing from
+    /// [`list_with_depth_stream`](crate::list_with_depth_stream) is in whatever order the
+    /// store returned it.
+    pub fn write_list_result(&mut self, list_result: &ListResult, depth: usize) -> io::Result<()> {
+        let objects = list_result
+            .objects
+            .iter()
+            .map(|object_meta| ListRecord::from_object_meta(object_meta, depth));
+        let common_prefixes =
+            list_result
+                .common_prefixes
+                .iter()
+                .map(|common_prefix| ListRecord::Prefix {
+                    location: common_prefix.as_ref(),
+                    depth,
+                });
+        let mut records: Vec<ListRecord<'_>> = objects.chain(common_prefixes).collect();
+        records.sort_unstable_by_key(ListRecord::location);
+        for record in &records {
+            self.write_record(record)?;
+        }
+        Ok(())
+    }
+
+    fn write_record(&mut self, record: &ListRecord<'_>) -> io::Result<()> {
+        let location = record.location();
+        for column in &self.columns {
+            match (column, record) {
+                (CsvColumn::Path, ListRecord::Object { .. }) => self.writer.write_field(location),
+                (CsvColumn::Path, ListRecord::Prefix { .. }) => {
+                    self.writer.write_field(format!("{location}/"))
+                }
+                (CsvColumn::ParentPrefix, _) => {
+                    let parent = location.rsplit_once('/').map_or("", |(parent, _)| parent);
+                    self.writer.write_field(parent)
+                }
+                (
+                    CsvColumn::Depth,
+                    ListRecord::Object { depth, .. } | ListRecord::Prefix { depth, .. },
+                ) => self.writer.write_field(depth.to_string()),
+                (CsvColumn::Size, ListRecord::Object { size, .. }) => {
+                    self.writer.write_field(size.to_string())
+                }
+                (CsvColumn::LastModified, ListRecord::Object { last_modified, .. }) => self
+                    .writer
+                    .write_field(last_modified.to_rfc3339_opts(SecondsFormat::AutoSi, true)),
+                (CsvColumn::ETag, ListRecord::Object { e_tag, .. }) => {
+                    self.writer.write_field(e_tag.unwrap_or_default())
+                }
+                (CsvColumn::Size | CsvColumn::LastModified | CsvColumn::ETag, _) => {
+                    self.writer.write_field("")
+                }
+            }?;
+        }
+        self.writer.write_record(None::<&[u8]>)?;
+        Ok(())
+    }
+
+    /// Flushes the rows written so far to the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Flushes and returns the underlying writer.
+    pub fn into_inner(self) -> io::Result<W> {
+        self.writer
+            .into_inner()
+            .map_err(|e| io::Error::new(e.error().kind(), e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use object_store::{path::Path, ObjectMeta};
+
+    use crate::{list_with_depth, test_utils::create_in_memory_store};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_csv_writer() {
+        let store = Arc::new(create_in_memory_store().await.unwrap());
+        let list_result = list_with_depth(store, Some(&Path::from("foo")), 1)
+            .await
+            .unwrap();
+        let columns = [
+            CsvColumn::Path,
+            CsvColumn::ParentPrefix,
+            CsvColumn::Depth,
+            CsvColumn::Size,
+        ];
+        let mut writer = CsvWriter::new(vec![], &columns).unwrap();
+        writer.write_list_result(&list_result, 1).unwrap();
+        let csv = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        let expected = "\
+path,parent_prefix,depth,size
+foo/bar/c.txt,foo/bar,1,0
+foo/bar/d.txt,foo/bar,1,0
+foo/baz/bleh/,foo/baz,1,
+foo/baz/e.txt,foo/baz,1,0
+";
+        assert_eq!(csv, expected);
+    }
+
+    #[test]
+    fn test_csv_quoting() {
+        // `Path::from` would percent-encode the quotes, but some stores return them as-is.
+        let object_meta = |key| ObjectMeta {
+            location: Path::parse(key).unwrap(),
+            last_modified: chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            size: 42,
+            e_tag: Some("\"abc\"".to_string()),
+            version: None,
+        };
+        let list_result = ListResult {
+            objects: vec![object_meta("a,b.txt"), object_meta("say \"hi\".txt")],
+            common_prefixes: vec![],
+        };
+        let mut writer = CsvWriter::new(vec![], &CsvColumn::ALL).unwrap();
+        writer.write_list_result(&list_result, 0).unwrap();
+        let csv = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        let expected = r#"path,parent_prefix,depth,size,last_modified,e_tag
+"a,b.txt",,0,42,2023-11-14T22:13:20Z,"""abc"""
+"say ""hi"".txt",,0,42,2023-11-14T22:13:20Z,"""abc"""
+"#;
+        assert_eq!(csv, expected);
+
+        let mut reader = csv::Reader::from_reader(csv.as_bytes());
+        let paths: Vec<String> = reader
+            .records()
+            .map(|record| record.unwrap()[0].to_string())
+            .collect();
+        assert_eq!(paths, vec!["a,b.txt", "say \"hi\".txt"]);
+    }
+
+    #[test]
+    fn test_csv_writer_sorts_rows() {
+        let object_meta = |key| ObjectMeta {
+            location: Path::from(key),
+            last_modified: chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            size: 1,
+            e_tag: None,
+            version: None,
+        };
+        let listing = PrefixListing {
+            prefix: Path::from("foo"),
+            depth: 0,
+            list_result: ListResult {
+                objects: vec![object_meta("foo/c.txt"), object_meta("foo/a.txt")],
+                common_prefixes: vec![Path::from("foo/d"), Path::from("foo/b")],
+            },
+        };
+        let mut writer = CsvWriter::new(vec![], &[CsvColumn::Path]).unwrap();
+        writer.write_listing(&listing).unwrap();
+        let csv = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(csv, "path\nfoo/a.txt\nfoo/b/\nfoo/c.txt\nfoo/d/\n");
+    }
+}
@@ -0,0 +1,49 @@
+use object_store::{path::Path, ObjectMeta};
+
+/// The total size of everything beneath a prefix, from
+/// [`disk_usage`](crate::disk_usage).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiskUsage {
+    /// The prefix. This is the empty path for the root of the store.
+    pub prefix: Path,
+    /// The number of objects beneath `prefix`, at any depth.
+    pub objects: usize,
+    /// The total size of those objects, in bytes.
+    pub bytes: u64,
+    /// The object with the most recent `last_modified`. `None` if there are no objects.
+    pub newest: Option<ObjectMeta>,
+    /// The object with the least recent `last_modified`. `None` if there are no objects.
+    pub oldest: Option<ObjectMeta>,
+}
+
+impl DiskUsage {
+    pub(crate) fn new(prefix: Path) -> Self {
+        Self {
+            prefix,
+            objects: 0,
+            bytes: 0,
+            newest: None,
+            oldest: None,
+        }
+    }
+
+    pub(crate) fn add(&mut self, object_meta: &ObjectMeta) {
+        self.objects += 1;
+        self.bytes += object_meta.size as u64;
+        let last_modified = object_meta.last_modified;
+        if self
+            .newest
+            .as_ref()
+            .is_none_or(|newest| last_modified > newest.last_modified)
+        {
+            self.newest = Some(object_meta.clone());
+        }
+        if self
+            .oldest
+            .as_ref()
+            .is_none_or(|oldest| last_modified < oldest.last_modified)
+        {
+            self.oldest = Some(object_meta.clone());
+        }
+    }
+}
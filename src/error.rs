@@ -0,0 +1,130 @@
+use std::fmt;
+
+use object_store::path::Path;
+
+use crate::GlobError;
+
+/// A specialized `Result` for this crate's [`Error`].
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The error type returned by this crate.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Listing a single prefix failed.
+    List {
+        /// The prefix which couldn't be listed. This is the empty path for the root of
+        /// the store.
+        path: Path,
+        /// The depth of `path`, relative to the prefix that the traversal started from.
+        depth: usize,
+        /// The prefixes which were listed on the way down to `path`, starting with the
+        /// prefix that the traversal started from, and ending with `path`'s parent.
+        /// The root of the store is not included.
+        parents: Vec<Path>,
+        /// The error returned by the object store.
+        source: object_store::Error,
+    },
+    /// The [deadline](crate::ListOptions::deadline) passed before `path` could be listed.
+    DeadlineExceeded {
+        /// The prefix which wasn't listed.
+        path: Path,
+        /// The depth of `path`, relative to the prefix that the traversal started from.
+        depth: usize,
+        /// The prefixes which were listed on the way down to `path`, as for [`Error::List`].
+        parents: Vec<Path>,
+    },
+    /// The glob pattern is invalid.
+    InvalidGlob(GlobError),
+    /// A spawned task panicked or was cancelled.
+    Join(tokio::task::JoinError),
+    /// Writing a [`Checkpoint`](crate::Checkpoint) to
+    /// [`ListOptions::checkpoint_file`](crate::ListOptions) failed.
+    Checkpoint(std::io::Error),
+}
+
+impl Error {
+    /// The prefix which caused this error, if the error relates to a single prefix.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::List { path, .. } | Self::DeadlineExceeded { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// The depth of the prefix which caused this error, if the error relates to a
+    /// single prefix.
+    pub fn depth(&self) -> Option<usize> {
+        match self {
+            Self::List { depth, .. } | Self::DeadlineExceeded { depth, .. } => Some(*depth),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::List {
+                path,
+                depth,
+                parents,
+                source,
+            } => {
+                write!(
+                    f,
+                    "failed to list prefix {:?} at depth {depth}",
+                    path.as_ref()
+                )?;
+                if !parents.is_empty() {
+                    let parents: Vec<&str> = parents.iter().map(|p| p.as_ref()).collect();
+                    write!(f, " (via {:?})", parents)?;
+                }
+                write!(f, ": {source}")
+            }
+            Self::DeadlineExceeded { path, depth, .. } => write!(
+                f,
+                "the deadline passed before prefix {:?} at depth {depth} could be listed",
+                path.as_ref()
+            ),
+            Self::InvalidGlob(e) => e.fmt(f),
+            Self::Join(e) => write!(f, "error joining spawned task: {e}"),
+            Self::Checkpoint(e) => write!(f, "failed to write checkpoint: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::List { source, .. } => Some(source),
+            Self::InvalidGlob(e) => Some(e),
+            Self::Join(e) => Some(e),
+            Self::Checkpoint(e) => Some(e),
+            Self::DeadlineExceeded { .. } => None,
+        }
+    }
+}
+
+impl From<GlobError> for Error {
+    fn from(e: GlobError) -> Self {
+        Self::InvalidGlob(e)
+    }
+}
+
+impl From<tokio::task::JoinError> for Error {
+    fn from(e: tokio::task::JoinError) -> Self {
+        Self::Join(e)
+    }
+}
+
+/// Allows `?` to be used on this crate's results within functions which return an
+/// [`object_store::Result`].
+impl From<Error> for object_store::Error {
+    fn from(e: Error) -> Self {
+        Self::Generic {
+            store: "list_with_depth",
+            source: Box::new(e),
+        }
+    }
+}
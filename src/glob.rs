@@ -0,0 +1,561 @@
+//! Glob patterns which are matched one path segment at a time, so that the traversal
+//! can prune common prefixes which can't possibly match.
+use std::{fmt, str::FromStr};
+
+use async_trait::async_trait;
+use object_store::path::{Path, DELIMITER};
+
+use crate::ListVisitor;
+
+/// A glob pattern such as `data/*/2024-*/*.nc`, used by [`list_glob`](crate::list_glob).
+///
+/// The pattern is split into segments on `/`, and each segment is matched against exactly
+/// one segment of the path. Within a segment:
+/// - `*` matches any sequence of characters (including the empty sequence).
+/// - `?` matches any single character.
+/// - `[abc]`, `[a-z]` match any one of the listed characters or ranges.
+///   `[!a-z]` (or `[^a-z]`) matches any character *not* listed.
+/// - `{a,b}` matches either `a` or `b`. Alternatives may contain other patterns,
+///   including nested `{..}`. The alternations in a segment can't expand to more than
+///   4096 combinations: `{a,b}{c,d}` is four.
+/// - `\` escapes the next character, so `\*` matches a literal `*`.
+///
+/// A segment which is exactly `**` (a "globstar") matches zero or more whole segments,
+/// so `data/**/*.nc` matches `data/a.nc`, `data/x/a.nc`, `data/x/y/z/a.nc`, etc.
+///
+/// Segments are matched against the [`Path`]s exactly as the object store returns them,
+/// without any percent-decoding.
+#[derive(Debug, Clone)]
+pub struct Glob {
+    pattern: String,
+    segments: Vec<Segment>,
+}
+
+impl Glob {
+    /// Parses `pattern`. Leading and trailing slashes are ignored.
+    pub fn new(pattern: &str) -> Result<Self, GlobError> {
+        let trimmed = pattern.trim_matches(DELIMITER.chars().next().unwrap());
+        if trimmed.is_empty() {
+            return Err(GlobError::new(pattern, "the pattern is empty"));
+        }
+        let segments = trimmed
+            .split(DELIMITER)
+            .map(|segment| Segment::parse(segment).map_err(|msg| GlobError::new(pattern, msg)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            pattern: pattern.to_string(),
+            segments,
+        })
+    }
+
+    /// The pattern that this `Glob` was created from.
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Returns `true` if `path` matches this pattern.
+    pub fn matches(&self, path: &Path) -> bool {
+        self.states_after(path)[self.segments.len()]
+    }
+
+    /// Returns `true` if some path beneath `prefix` could match this pattern.
+    pub(crate) fn could_match_below(&self, prefix: &Path) -> bool {
+        // If any segment is still waiting to be matched then a longer path might match.
+        self.states_after(prefix)[..self.segments.len()]
+            .iter()
+            .any(|&state| state)
+    }
+
+    /// Runs `path` through the pattern, treated as a non-deterministic finite automaton
+    /// over path segments. Returns one flag per position in `self.segments` (plus one for
+    /// "matched every segment"), which is `true` if that position can be reached.
+    fn states_after(&self, path: &Path) -> Vec<bool> {
+        let n = self.segments.len();
+        let mut states = vec![false; n + 1];
+        states[0] = true;
+        self.skip_globstars(&mut states);
+        for part in path.parts() {
+            let mut next = vec![false; n + 1];
+            for (i, segment) in self.segments.iter().enumerate() {
+                if !states[i] {
+                    continue;
+                }
+                match segment {
+                    // A globstar can consume any number of segments, so we stay put.
+                    Segment::Globstar => next[i] = true,
+                    Segment::Pattern(_) if segment.matches(part.as_ref()) => next[i + 1] = true,
+                    Segment::Pattern(_) => {}
+                }
+            }
+            self.skip_globstars(&mut next);
+            states = next;
+        }
+        states
+    }
+
+    /// A globstar can match zero segments, so reaching a globstar also reaches the
+    /// position after it.
+    fn skip_globstars(&self, states: &mut [bool]) {
+        for (i, segment) in self.segments.iter().enumerate() {
+            if states[i] && matches!(segment, Segment::Globstar) {
+                states[i + 1] = true;
+            }
+        }
+    }
+
+    /// The longest run of literal segments at the start of the pattern. Listing can start
+    /// here, instead of at the root of the store. The last segment is never included,
+    /// so the prefix is always a proper ancestor of any match.
+    pub(crate) fn literal_prefix(&self) -> Option<Path> {
+        let literals: Vec<String> = self.segments[..self.segments.len() - 1]
+            .iter()
+            .map_while(Segment::as_literal)
+            .collect();
+        if literals.is_empty() {
+            None
+        } else {
+            // Use `Path::parse` rather than `Path::from` so the literal isn't percent-encoded:
+            // the pattern is matched against paths exactly as the store returns them.
+            Path::parse(literals.join(DELIMITER)).ok()
+        }
+    }
+
+    /// The depth (relative to [`Self::literal_prefix`]) of the deepest prefix that
+    /// needs to be listed, or `None` if the pattern contains a globstar (in which case
+    /// the depth is unbounded).
+    pub(crate) fn max_depth(&self) -> Option<usize> {
+        if self
+            .segments
+            .iter()
+            .any(|segment| matches!(segment, Segment::Globstar))
+        {
+            return None;
+        }
+        let n_literals = self
+            .literal_prefix()
+            .map_or(0, |prefix| prefix.parts().count());
+        Some(self.segments.len() - 1 - n_literals)
+    }
+}
+
+impl FromStr for Glob {
+    type Err = GlobError;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        Self::new(pattern)
+    }
+}
+
+impl fmt::Display for Glob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.pattern)
+    }
+}
+
+/// Only descends into the prefixes which could contain a match.
+#[async_trait]
+impl ListVisitor for Glob {
+    async fn on_prefix(&self, prefix: &Path, _depth: usize) -> bool {
+        self.could_match_below(prefix)
+    }
+}
+
+/// The error returned when a glob pattern can't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlobError {
+    pattern: String,
+    msg: &'static str,
+}
+
+impl GlobError {
+    fn new(pattern: &str, msg: &'static str) -> Self {
+        Self {
+            pattern: pattern.to_string(),
+            msg,
+        }
+    }
+}
+
+impl fmt::Display for GlobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid glob pattern {:?}: {}", self.pattern, self.msg)
+    }
+}
+
+impl std::error::Error for GlobError {}
+
+/// A single segment of a [`Glob`].
+#[derive(Debug, Clone)]
+enum Segment {
+    /// `**`, which matches zero or more path segments.
+    Globstar,
+    /// Matches exactly one path segment. Any `{a,b}` alternations have been expanded.
+    Pattern(Vec<Vec<Token>>),
+}
+
+impl Segment {
+    fn parse(segment: &str) -> Result<Self, &'static str> {
+        if segment.is_empty() {
+            return Err("the pattern contains an empty segment");
+        }
+        if segment == "**" {
+            return Ok(Self::Globstar);
+        }
+        let chars: Vec<char> = segment.chars().collect();
+        let mut pos = 0;
+        let nodes = parse_nodes(&chars, &mut pos, false)?;
+        Ok(Self::Pattern(expand(&nodes)?))
+    }
+
+    fn matches(&self, part: &str) -> bool {
+        let chars: Vec<char> = part.chars().collect();
+        match self {
+            Self::Globstar => true,
+            Self::Pattern(alternatives) => alternatives
+                .iter()
+                .any(|tokens| match_tokens(tokens, &chars)),
+        }
+    }
+
+    /// Returns the segment as a string if it only contains literal characters.
+    fn as_literal(&self) -> Option<String> {
+        match self {
+            Self::Pattern(alternatives) if alternatives.len() == 1 => alternatives[0]
+                .iter()
+                .map(|token| match token {
+                    Token::Char(c) => Some(*c),
+                    _ => None,
+                })
+                .collect(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Char(char),
+    AnyChar,
+    AnyString,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Token {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Token::Char(expected) => *expected == c,
+            Token::AnyChar => true,
+            Token::AnyString => unreachable!("`*` is handled by match_tokens"),
+            Token::Class { negated, ranges } => {
+                ranges.iter().any(|(lo, hi)| (*lo..=*hi).contains(&c)) != *negated
+            }
+        }
+    }
+}
+
+/// A parsed segment, before `{a,b}` alternations are expanded.
+#[derive(Debug)]
+enum Node {
+    Token(Token),
+    Alternation(Vec<Vec<Node>>),
+}
+
+/// Parses until the end of `chars`, or (if `in_braces`) until an unmatched `,` or `}`.
+fn parse_nodes(
+    chars: &[char],
+    pos: &mut usize,
+    in_braces: bool,
+) -> Result<Vec<Node>, &'static str> {
+    let mut nodes = vec![];
+    while let Some(&c) = chars.get(*pos) {
+        match c {
+            ',' | '}' if in_braces => return Ok(nodes),
+            '}' => return Err("unmatched `}`"),
+            '\\' => {
+                let escaped = chars.get(*pos + 1).ok_or("trailing `\\`")?;
+                nodes.push(Node::Token(Token::Char(*escaped)));
+                *pos += 2;
+            }
+            '*' => {
+                // Consecutive stars are equivalent to a single star.
+                if !matches!(nodes.last(), Some(Node::Token(Token::AnyString))) {
+                    nodes.push(Node::Token(Token::AnyString));
+                }
+                *pos += 1;
+            }
+            '?' => {
+                nodes.push(Node::Token(Token::AnyChar));
+                *pos += 1;
+            }
+            '[' => {
+                *pos += 1;
+                nodes.push(Node::Token(parse_class(chars, pos)?));
+            }
+            '{' => {
+                *pos += 1;
+                let mut alternatives = vec![];
+                loop {
+                    alternatives.push(parse_nodes(chars, pos, true)?);
+                    match chars.get(*pos) {
+                        Some(',') => *pos += 1,
+                        Some('}') => {
+                            *pos += 1;
+                            break;
+                        }
+                        _ => return Err("unclosed `{`"),
+                    }
+                }
+                nodes.push(Node::Alternation(alternatives));
+            }
+            c => {
+                nodes.push(Node::Token(Token::Char(c)));
+                *pos += 1;
+            }
+        }
+    }
+    if in_braces {
+        Err("unclosed `{`")
+    } else {
+        Ok(nodes)
+    }
+}
+
+/// Parses a character class. `pos` must point just after the opening `[`.
+fn parse_class(chars: &[char], pos: &mut usize) -> Result<Token, &'static str> {
+    let negated = matches!(chars.get(*pos), Some('!' | '^'));
+    if negated {
+        *pos += 1;
+    }
+    let mut ranges = vec![];
+    let mut first = true;
+    loop {
+        let c = match chars.get(*pos) {
+            None => return Err("unclosed `[`"),
+            // A `]` straight after the `[` is a literal `]`.
+            Some(']') if !first => {
+                *pos += 1;
+                break;
+            }
+            Some('\\') => {
+                *pos += 1;
+                *chars.get(*pos).ok_or("trailing `\\`")?
+            }
+            Some(&c) => c,
+        };
+        *pos += 1;
+        first = false;
+        if chars.get(*pos) == Some(&'-') && chars.get(*pos + 1).is_some_and(|&c| c != ']') {
+            let hi = chars[*pos + 1];
+            if hi < c {
+                return Err("invalid character range");
+            }
+            ranges.push((c, hi));
+            *pos += 2;
+        } else {
+            ranges.push((c, c));
+        }
+    }
+    Ok(Token::Class { negated, ranges })
+}
+
+/// The most token sequences that a segment's `{a,b}` alternations may expand to. Each
+/// one has to be tried against every path segment, and they multiply: twenty `{a,b}`s
+/// would be a million sequences.
+const MAX_ALTERNATIVES: usize = 4096;
+
+/// Expands all the alternations in `nodes` into a flat list of token sequences, or
+/// returns an error if there would be more than [`MAX_ALTERNATIVES`] of them.
+fn expand(nodes: &[Node]) -> Result<Vec<Vec<Token>>, &'static str> {
+    let mut expanded = vec![vec![]];
+    for node in nodes {
+        match node {
+            Node::Token(token) => expanded.iter_mut().for_each(|seq| seq.push(token.clone())),
+            Node::Alternation(alternatives) => {
+                let mut suffixes: Vec<Vec<Token>> = vec![];
+                for alternative in alternatives {
+                    suffixes.extend(expand(alternative)?);
+                }
+                if expanded.len().saturating_mul(suffixes.len()) > MAX_ALTERNATIVES {
+                    return Err("too many `{..}` alternatives");
+                }
+                expanded = expanded
+                    .iter()
+                    .flat_map(|prefix| {
+                        suffixes
+                            .iter()
+                            .map(move |suffix| [prefix.as_slice(), suffix].concat())
+                    })
+                    .collect();
+            }
+        }
+    }
+    Ok(expanded)
+}
+
+/// Matches `chars` against `tokens` in `O(tokens.len() * chars.len())` time.
+///
+/// When a token fails to match, only the most recent `*` needs to be retried (consuming
+/// one more character): an earlier `*` could only consume more characters by
+/// making the text that the later `*` has to cover shorter, which can't help it match.
+fn match_tokens(tokens: &[Token], chars: &[char]) -> bool {
+    let (mut t, mut c) = (0, 0);
+    // The position of the last `*` seen, and of the character it'll consume next.
+    let mut star = None;
+    while c < chars.len() {
+        match tokens.get(t) {
+            Some(Token::AnyString) => {
+                star = Some((t, c));
+                t += 1;
+            }
+            Some(token) if token.matches(chars[c]) => {
+                t += 1;
+                c += 1;
+            }
+            _ => match star {
+                Some((star_t, star_c)) => {
+                    star = Some((star_t, star_c + 1));
+                    t = star_t + 1;
+                    c = star_c + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    tokens[t..]
+        .iter()
+        .all(|token| matches!(token, Token::AnyString))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob(pattern: &str) -> Glob {
+        Glob::new(pattern).unwrap()
+    }
+
+    #[test]
+    fn test_wildcards() {
+        let g = glob("data/*/2024-??.nc");
+        assert!(g.matches(&Path::from("data/x/2024-01.nc")));
+        assert!(g.matches(&Path::from("data/yy/2024-12.nc")));
+        assert!(!g.matches(&Path::from("data/x/2024-1.nc")));
+        assert!(!g.matches(&Path::from("data/x/y/2024-01.nc")));
+        assert!(!g.matches(&Path::from("data/2024-01.nc")));
+    }
+
+    #[test]
+    fn test_character_classes() {
+        let g = glob("[a-c]x[!0-9]");
+        assert!(g.matches(&Path::from("bxy")));
+        assert!(!g.matches(&Path::from("dxy")));
+        assert!(!g.matches(&Path::from("bx1")));
+        assert!(glob("[]]").matches(&Path::parse("]").unwrap()));
+        assert!(glob("[a-]").matches(&Path::from("-")));
+    }
+
+    #[test]
+    fn test_alternation() {
+        let g = glob("{foo,ba{r,z}*}/*.{nc,zarr}");
+        assert!(g.matches(&Path::from("foo/a.nc")));
+        assert!(g.matches(&Path::from("bar/a.zarr")));
+        assert!(g.matches(&Path::from("bazooka/a.nc")));
+        assert!(!g.matches(&Path::from("bat/a.nc")));
+        assert!(!g.matches(&Path::from("foo/a.txt")));
+    }
+
+    #[test]
+    fn test_escape() {
+        let g = glob(r"a\*");
+        assert!(g.matches(&Path::parse("a*").unwrap()));
+        assert!(!g.matches(&Path::from("ab")));
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        for pattern in ["", "/", "a//b", "[abc", "{a,b", "a}", "[z-a]", "a\\"] {
+            assert!(Glob::new(pattern).is_err(), "{pattern:?} should be invalid");
+        }
+    }
+
+    #[test]
+    fn test_pathological_patterns() {
+        // With backtracking on every `*` this takes exponential time.
+        let g = glob("*a*a*a*a*a*a*b");
+        let part = "a".repeat(60);
+        assert!(!g.matches(&Path::from(part.as_str())));
+        assert!(g.matches(&Path::from(format!("{part}b"))));
+
+        let pattern = "{a,b}".repeat(20);
+        let err = Glob::new(&pattern).unwrap_err();
+        assert!(err.to_string().contains("too many"), "{err}");
+        // Alternatives which add up (rather than multiply) are fine.
+        let pattern = format!("{{{}}}", vec!["x"; 1000].join(","));
+        assert!(glob(&pattern).matches(&Path::from("x")));
+    }
+
+    #[test]
+    fn test_literal_prefix_and_max_depth() {
+        let g = glob("/data/2024/*/x.nc");
+        assert_eq!(g.literal_prefix(), Some(Path::from("data/2024")));
+        assert_eq!(g.max_depth(), Some(1));
+
+        let g = glob("data/file.nc");
+        assert_eq!(g.literal_prefix(), Some(Path::from("data")));
+        assert_eq!(g.max_depth(), Some(0));
+
+        let g = glob("*.nc");
+        assert_eq!(g.literal_prefix(), None);
+        assert_eq!(g.max_depth(), Some(0));
+
+        let g = glob("data/**/*.nc");
+        assert_eq!(g.literal_prefix(), Some(Path::from("data")));
+        assert_eq!(g.max_depth(), None);
+    }
+
+    #[test]
+    fn test_could_match_below() {
+        let g = glob("data/*/2024-*/*.nc");
+        assert!(g.could_match_below(&Path::from("data")));
+        assert!(g.could_match_below(&Path::from("data/x")));
+        assert!(g.could_match_below(&Path::from("data/x/2024-01")));
+        assert!(!g.could_match_below(&Path::from("data/x/2023-01")));
+        assert!(!g.could_match_below(&Path::from("other")));
+        assert!(!g.could_match_below(&Path::from("data/x/2024-01/y")));
+    }
+
+    #[test]
+    fn test_globstar() {
+        let g = glob("data/**/*.nc");
+        assert!(g.matches(&Path::from("data/a.nc")));
+        assert!(g.matches(&Path::from("data/x/a.nc")));
+        assert!(g.matches(&Path::from("data/x/y/z/a.nc")));
+        assert!(!g.matches(&Path::from("data/x/a.txt")));
+        assert!(!g.matches(&Path::from("other/x/a.nc")));
+
+        let g = glob("**/2024/**");
+        assert!(g.matches(&Path::from("2024")));
+        assert!(g.matches(&Path::from("a/b/2024/c/d")));
+        assert!(!g.matches(&Path::from("a/b/2023/c/d")));
+
+        // `**` within a segment is just a `*`.
+        let g = glob("a**b");
+        assert!(g.matches(&Path::from("axyzb")));
+        assert!(!g.matches(&Path::from("ax/yb")));
+    }
+
+    #[test]
+    fn test_globstar_could_match_below() {
+        let g = glob("data/**/zarr.json");
+        assert!(g.could_match_below(&Path::from("data")));
+        assert!(g.could_match_below(&Path::from("data/x/y/z")));
+        assert!(!g.could_match_below(&Path::from("other")));
+
+        let g = glob("*/**/{a,b}/*.nc");
+        assert!(g.could_match_below(&Path::from("x/y/a")));
+        assert!(g.could_match_below(&Path::from("x")));
+    }
+}
@@ -0,0 +1,235 @@
+use std::io::{self, Write};
+
+use chrono::{DateTime, Utc};
+use object_store::{ListResult, ObjectMeta};
+
+/// One object or common prefix from a listing, in the form written by [`write_json`] and
+/// [`write_ndjson`].
+///
+/// # Schema
+///
+/// Each record is a JSON object whose `type` field says which kind of record it is.
+/// Objects look like:
+///
+/// ```json
+/// {"type":"object","location":"foo/bar/c.txt","size":1024,"last_modified":"2024-05-01T12:34:56Z","e_tag":"\"6f5902ac\"","version":null,"depth":1}
+/// ```
+///
+/// | Field           | Type             | Meaning                                           |
+/// |-----------------|------------------|---------------------------------------------------|
+/// | `type`          | `"object"`       |                                                   |
+/// | `location`      | string           | The full path of the object, without a leading `/`. |
+/// | `size`          | integer          | The size of the object, in bytes.                 |
+/// | `last_modified` | string           | RFC 3339, in UTC, e.g. `2024-05-01T12:34:56Z`.    |
+/// | `e_tag`         | string or `null` | The object's ETag, if the store returned one.     |
+/// | `version`       | string or `null` | The object's version, if the store is versioned.  |
+/// | `depth`         | integer          | The depth of the listing which returned the object. |
+///
+/// Common prefixes look like:
+///
+/// ```json
+/// {"type":"prefix","location":"foo/bar","depth":1}
+/// ```
+///
+/// | Field      | Type       | Meaning                                                  |
+/// |------------|------------|----------------------------------------------------------|
+/// | `type`     | `"prefix"` |                                                          |
+/// | `location` | string     | The full path of the prefix, without a trailing `/`.     |
+/// | `depth`    | integer    | The depth of the listing which returned the prefix.      |
+///
+/// `depth` is the `depth` passed to [`list_with_depth`](crate::list_with_depth) (or
+/// [`PrefixListing::depth`](crate::PrefixListing::depth)): the objects and common
+/// prefixes directly beneath a prefix at that depth.
+///
+/// The schema is stable. New fields may be added, so consumers should ignore fields they
+/// don't recognise, but existing fields won't be renamed, removed or change type. (For the
+/// same reason, both variants are `#[non_exhaustive]`: match them with `..`.)
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ListRecord<'a> {
+    /// An object. Serialized with `"type":"object"`.
+    #[non_exhaustive]
+    Object {
+        /// The full path of the object, without a leading `/`.
+        location: &'a str,
+        /// The size of the object, in bytes.
+        size: usize,
+        /// When the object was last modified. Serialized in RFC 3339 format, in UTC.
+        last_modified: DateTime<Utc>,
+        /// The object's ETag, if the store returned one.
+        e_tag: Option<&'a str>,
+        /// The object's version, if the store is versioned.
+        version: Option<&'a str>,
+        /// The depth of the listing which returned the object.
+        depth: usize,
+    },
+    /// A common prefix. Serialized with `"type":"prefix"`.
+    #[non_exhaustive]
+    Prefix {
+        /// The full path of the prefix, without a trailing `/`.
+        location: &'a str,
+        /// The depth of the listing which returned the prefix.
+        depth: usize,
+    },
+}
+
+impl<'a> ListRecord<'a> {
+    /// The record for an object returned by a listing at `depth`.
+    pub fn from_object_meta(object_meta: &'a ObjectMeta, depth: usize) -> Self {
+        Self::Object {
+            location: object_meta.location.as_ref(),
+            size: object_meta.size,
+            last_modified: object_meta.last_modified,
+            e_tag: object_meta.e_tag.as_deref(),
+            version: object_meta.version.as_deref(),
+            depth,
+        }
+    }
+
+    /// Iterates over the objects and common prefixes in `list_result`, interleaved and in
+    /// order of path (like `ls`), assuming that both are already sorted, as they are in
+    /// the results of [`list_with_depth`](crate::list_with_depth).
+    pub fn from_list_result(
+        list_result: &'a ListResult,
+        depth: usize,
+    ) -> impl Iterator<Item = ListRecord<'a>> {
+        let mut common_prefixes = list_result.common_prefixes.iter().peekable();
+        let mut objects = list_result.objects.iter().peekable();
+        std::iter::from_fn(move || {
+            let next_is_prefix = match (common_prefixes.peek(), objects.peek()) {
+                (Some(common_prefix), Some(object_meta)) => **common_prefix < object_meta.location,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => return None,
+            };
+            Some(if next_is_prefix {
+                Self::Prefix {
+                    location: common_prefixes.next()?.as_ref(),
+                    depth,
+                }
+            } else {
+                Self::from_object_meta(objects.next()?, depth)
+            })
+        })
+    }
+
+    /// The full path of the object or prefix.
+    pub fn location(&self) -> &'a str {
+        match self {
+            Self::Object { location, .. } | Self::Prefix { location, .. } => location,
+        }
+    }
+}
+
+/// Writes `list_result` as newline-delimited JSON: one [`ListRecord`] per line, in order
+/// of path. `depth` is the depth at which `list_result` was listed.
+///
+/// Each line is written as soon as it's serialized, so wrap `out` in a
+/// [`BufWriter`](std::io::BufWriter) when writing to a file or stdout.
+pub fn write_ndjson(
+    out: &mut impl Write,
+    list_result: &ListResult,
+    depth: usize,
+) -> io::Result<()> {
+    for record in ListRecord::from_list_result(list_result, depth) {
+        serde_json::to_writer(&mut *out, &record)?;
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// Writes `list_result` as a single JSON array of [`ListRecord`]s, in order of path,
+/// followed by a newline. `depth` is the depth at which `list_result` was listed.
+pub fn write_json(out: &mut impl Write, list_result: &ListResult, depth: usize) -> io::Result<()> {
+    out.write_all(b"[")?;
+    for (i, record) in ListRecord::from_list_result(list_result, depth).enumerate() {
+        if i > 0 {
+            out.write_all(b",")?;
+        }
+        serde_json::to_writer(&mut *out, &record)?;
+    }
+    out.write_all(b"]\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use object_store::path::Path;
+
+    use crate::{list_with_depth, test_utils::create_in_memory_store};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_write_ndjson() {
+        let store = Arc::new(create_in_memory_store().await.unwrap());
+        let list_result = list_with_depth(store, Some(&Path::from("foo")), 1)
+            .await
+            .unwrap();
+        let mut out = vec![];
+        write_ndjson(&mut out, &list_result, 1).unwrap();
+        let lines: Vec<serde_json::Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        let locations: Vec<&str> = lines
+            .iter()
+            .map(|line| line["location"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            locations,
+            vec![
+                "foo/bar/c.txt",
+                "foo/bar/d.txt",
+                "foo/baz/bleh",
+                "foo/baz/e.txt"
+            ]
+        );
+        assert_eq!(
+            lines[2],
+            serde_json::json!({"type": "prefix", "location": "foo/baz/bleh", "depth": 1})
+        );
+        let object = lines[0].as_object().unwrap();
+        assert_eq!(object["type"], "object");
+        assert_eq!(object["size"], 0);
+        assert_eq!(object["depth"], 1);
+        assert!(object["version"].is_null());
+        let last_modified = object["last_modified"].as_str().unwrap();
+        assert!(DateTime::parse_from_rfc3339(last_modified).is_ok());
+    }
+
+    #[test]
+    fn test_write_json() {
+        let object_meta = ObjectMeta {
+            location: Path::from("b/c.txt"),
+            last_modified: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            size: 42,
+            e_tag: Some("abc".to_string()),
+            version: None,
+        };
+        let list_result = ListResult {
+            objects: vec![object_meta],
+            common_prefixes: vec![Path::from("a/d")],
+        };
+        let mut out = vec![];
+        write_json(&mut out, &list_result, 2).unwrap();
+        let expected = concat!(
+            r#"[{"type":"prefix","location":"a/d","depth":2},"#,
+            r#"{"type":"object","location":"b/c.txt","size":42,"#,
+            r#""last_modified":"2023-11-14T22:13:20Z","e_tag":"abc","version":null,"depth":2}]"#,
+            "\n"
+        );
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+
+        let mut out = vec![];
+        let empty = ListResult {
+            objects: vec![],
+            common_prefixes: vec![],
+        };
+        write_json(&mut out, &empty, 0).unwrap();
+        assert_eq!(out, b"[]\n");
+    }
+}
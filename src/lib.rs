@@ -1,87 +1,611 @@
 #![doc = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/README.md"))]
-use std::{future::Future, pin::Pin, sync::Arc};
+use std::{collections::HashMap, ops::RangeInclusive, sync::Arc};
 
+use futures::StreamExt;
 use object_store::{path::Path, ListResult, ObjectStore};
-use tokio::task::JoinSet;
+
+mod adaptive;
+#[cfg(feature = "arrow")]
+mod arrow_export;
+mod checkpoint;
+#[cfg(feature = "csv")]
+mod csv_export;
+mod disk_usage;
+mod error;
+mod glob;
+#[cfg(feature = "serde")]
+mod json;
+mod options;
+#[cfg(feature = "parquet")]
+mod parquet_export;
+mod progress;
+mod rate_limit;
+mod render;
+mod retry;
+mod stats;
+mod traverse;
+mod tree;
+mod visitor;
+
+pub use adaptive::{is_throttling, AdaptiveConcurrency};
+#[cfg(feature = "arrow")]
+pub use arrow_export::{objects_schema, objects_to_record_batch};
+pub use checkpoint::Checkpoint;
+#[cfg(feature = "serde")]
+pub use checkpoint::CheckpointFile;
+#[cfg(feature = "csv")]
+pub use csv_export::{CsvColumn, CsvWriter};
+pub use disk_usage::DiskUsage;
+pub use error::{Error, Result};
+pub use glob::{Glob, GlobError};
+#[cfg(feature = "serde")]
+pub use json::{write_json, write_ndjson, ListRecord};
+pub use options::ListOptions;
+#[cfg(feature = "parquet")]
+pub use parquet_export::ParquetWriter;
+pub use progress::{ListProgress, ProgressSnapshot};
+pub use rate_limit::RateLimiter;
+pub use render::{write_tree, Charset, TreeFormat};
+pub use retry::{is_retryable, RetryConfig};
+pub use stats::ListStats;
+pub use traverse::{ListStream, PrefixListing};
+pub use tree::ListTree;
+pub use visitor::ListVisitor;
 
 #[doc = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/README.md"))]
 pub async fn list_with_depth(
     store: Arc<dyn ObjectStore>,
     prefix: Option<&Path>,
     depth: usize,
-) -> object_store::Result<ListResult> {
-    let list_result = store.list_with_delimiter(prefix).await?;
-    next_level(store, list_result, 0, depth).await
-}
-
-fn next_level(
-    store: Arc<dyn ObjectStore>,
-    list_result: ListResult,
-    depth_of_list_result: usize,
-    target_depth: usize,
-) -> Pin<Box<dyn Future<Output = std::result::Result<ListResult, object_store::Error>> + Send>> {
-    // See here for why we're using `Box::pin`:
-    // https://stackoverflow.com/a/67030773
-    Box::pin(async move {
-        // Base case:
-        if depth_of_list_result == target_depth {
-            return Ok(list_result);
-        }
-
-        let mut set = JoinSet::new();
-        for common_prefix in list_result.common_prefixes {
-            let inner_store = store.clone();
-            set.spawn(async move {
-                let next_list_result = inner_store
-                    .list_with_delimiter(Some(&common_prefix))
-                    .await?;
-
-                // Recursive call to next_level:
-                next_level(
-                    inner_store,
-                    next_list_result,
-                    depth_of_list_result + 1,
-                    target_depth,
-                )
-                .await
+) -> Result<ListResult> {
+    list_with_depth_opts(store, prefix, depth, ListOptions::default()).await
+}
+
+/// Like [`list_with_depth`] but with [`ListOptions`], e.g. to limit the number of
+/// concurrent requests.
+///
+/// If `ListOptions::checkpoint_file` (which needs the `serde` feature) is set and the file
+/// holds the checkpoint of an unfinished listing (which may have crashed), this fails with
+/// [`Error::Checkpoint`] rather than overwrite it. Resume from it with `Checkpoint::load`
+/// and [`resume_list_with_depth_opts`], or delete it to start again. The checkpoint of a
+/// finished listing is overwritten.
+pub async fn list_with_depth_opts(
+    store: Arc<dyn ObjectStore>,
+    prefix: Option<&Path>,
+    depth: usize,
+    options: ListOptions,
+) -> Result<ListResult> {
+    #[cfg(feature = "serde")]
+    if let Some(checkpoint_file) = &options.checkpoint_file {
+        let path = &checkpoint_file.path;
+        if path.try_exists().map_err(Error::Checkpoint)?
+            && !Checkpoint::load(path)
+                .map_err(Error::Checkpoint)?
+                .is_complete()
+        {
+            let msg = format!(
+                "{} holds an unfinished listing: resume from it with Checkpoint::load and \
+                 resume_list_with_depth_opts, or delete it to start again",
+                path.display()
+            );
+            return Err(Error::Checkpoint(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                msg,
+            )));
+        }
+        let checkpoint = Checkpoint::new(prefix, depth);
+        return resume_list_with_depth_opts(store, checkpoint, options).await;
+    }
+    list_with_depth_range_opts(store, prefix, depth..=depth, options).await
+}
+
+/// Continues a [`list_with_depth`] listing from a [`Checkpoint`], and returns the same
+/// result that `list_with_depth` would have returned. Only the prefixes which are still
+/// pending are listed.
+pub async fn resume_list_with_depth(
+    store: Arc<dyn ObjectStore>,
+    checkpoint: Checkpoint,
+) -> Result<ListResult> {
+    resume_list_with_depth_opts(store, checkpoint, ListOptions::default()).await
+}
+
+/// Like [`resume_list_with_depth`] but with [`ListOptions`], e.g. to keep writing
+/// checkpoints to `ListOptions::checkpoint_file` (which needs the `serde` feature).
+///
+/// If the listing fails, its error is returned even if writing the final checkpoint
+/// fails too (which is logged, with the `tracing` feature).
+pub async fn resume_list_with_depth_opts(
+    store: Arc<dyn ObjectStore>,
+    mut checkpoint: Checkpoint,
+    options: ListOptions,
+) -> Result<ListResult> {
+    let mut autosave = checkpoint::Autosave::new(&options);
+    // We need the listings at every depth (not just `checkpoint.depth`) to keep track of
+    // which prefixes are pending.
+    let mut stream = traverse::spawn_traversal_from(
+        store,
+        checkpoint.frontier(),
+        0..=checkpoint.depth,
+        None,
+        false,
+        options,
+    );
+    while let Some(prefix_listing) = stream.next().await {
+        match prefix_listing {
+            Ok(prefix_listing) => checkpoint.record(prefix_listing),
+            Err(e) => {
+                // The listing's error is the one to return: the checkpoint is only there
+                // to resume from after fixing it.
+                if let Err(_save_err) = autosave.save(&mut checkpoint).await {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(error = %_save_err, "couldn't write checkpoint");
+                }
+                return Err(e);
+            }
+        }
+        autosave
+            .tick(&mut checkpoint)
+            .await
+            .map_err(Error::Checkpoint)?;
+    }
+    autosave
+        .save(&mut checkpoint)
+        .await
+        .map_err(Error::Checkpoint)?;
+    let mut list_result = checkpoint.list_result;
+    sort_list_result(&mut list_result);
+    Ok(list_result)
+}
+
+/// Like [`list_with_depth`], but also returns [`ListStats`] about the traversal, such as
+/// the number of requests made.
+pub async fn list_with_depth_stats(
+    store: Arc<dyn ObjectStore>,
+    prefix: Option<&Path>,
+    depth: usize,
+) -> Result<(ListResult, ListStats)> {
+    list_with_depth_stats_opts(store, prefix, depth, ListOptions::default()).await
+}
+
+/// Like [`list_with_depth_stats`] but with [`ListOptions`].
+pub async fn list_with_depth_stats_opts(
+    store: Arc<dyn ObjectStore>,
+    prefix: Option<&Path>,
+    depth: usize,
+    options: ListOptions,
+) -> Result<(ListResult, ListStats)> {
+    let mut combined = ListResult {
+        objects: vec![],
+        common_prefixes: vec![],
+    };
+    let mut stream = traverse::spawn_traversal(store, prefix, depth..=depth, None, false, options);
+    while let Some(prefix_listing) = stream.next().await {
+        let PrefixListing { list_result, .. } = prefix_listing?;
+        combined.objects.extend(list_result.objects);
+        combined.common_prefixes.extend(list_result.common_prefixes);
+    }
+    sort_list_result(&mut combined);
+    Ok((combined, stream.stats()))
+}
+
+/// Lists `prefix` down to `depth`, like [`list_with_depth`], but returns a [`ListTree`]
+/// which keeps every level of the listing, and which objects belong to which prefix.
+pub async fn list_tree(
+    store: Arc<dyn ObjectStore>,
+    prefix: Option<&Path>,
+    depth: usize,
+) -> Result<ListTree> {
+    list_tree_opts(store, prefix, depth, ListOptions::default()).await
+}
+
+/// Like [`list_tree`] but with [`ListOptions`].
+pub async fn list_tree_opts(
+    store: Arc<dyn ObjectStore>,
+    prefix: Option<&Path>,
+    depth: usize,
+    options: ListOptions,
+) -> Result<ListTree> {
+    let mut listings = HashMap::new();
+    let mut stream = traverse::spawn_traversal(store, prefix, 0..=depth, None, false, options);
+    while let Some(prefix_listing) = stream.next().await {
+        let prefix_listing = prefix_listing?;
+        listings.insert(prefix_listing.prefix.clone(), prefix_listing);
+    }
+    let root = prefix.cloned().unwrap_or_default();
+    Ok(ListTree::from_listings(&root, depth, listings))
+}
+
+/// Lists every depth from `0` to `max_depth` in a single traversal, and returns one
+/// [`ListResult`] per depth.
+///
+/// `list_levels(store, prefix, n)[d]` is the same as `list_with_depth(store, prefix, d)`,
+/// but each prefix is only listed once. So `list_levels(store, None, 2)` is like `ls`,
+/// `ls *` and `ls */*`. Each `ListResult` is sorted by path.
+///
+/// There are fewer than `max_depth + 1` levels if the tree isn't that deep: the last
+/// level has no common prefixes, so every deeper level would be empty. So
+/// `max_depth = usize::MAX` lists every level.
+pub async fn list_levels(
+    store: Arc<dyn ObjectStore>,
+    prefix: Option<&Path>,
+    max_depth: usize,
+) -> Result<Vec<ListResult>> {
+    list_levels_opts(store, prefix, max_depth, ListOptions::default()).await
+}
+
+/// Like [`list_levels`] but with [`ListOptions`].
+pub async fn list_levels_opts(
+    store: Arc<dyn ObjectStore>,
+    prefix: Option<&Path>,
+    max_depth: usize,
+    options: ListOptions,
+) -> Result<Vec<ListResult>> {
+    let mut levels: Vec<ListResult> = vec![];
+    let mut stream = traverse::spawn_traversal(store, prefix, 0..=max_depth, None, false, options);
+    while let Some(prefix_listing) = stream.next().await {
+        let PrefixListing {
+            depth, list_result, ..
+        } = prefix_listing?;
+        // Listings don't arrive in order of depth, but every level above this one has
+        // already had at least one listing (this prefix's parent).
+        if levels.len() <= depth {
+            levels.resize_with(depth + 1, || ListResult {
+                objects: vec![],
+                common_prefixes: vec![],
             });
         }
+        let level = &mut levels[depth];
+        level.objects.extend(list_result.objects);
+        level.common_prefixes.extend(list_result.common_prefixes);
+    }
+    levels.iter_mut().for_each(sort_list_result);
+    Ok(levels)
+}
 
-        // Extract results and propagate errors:
-        let mut combined = ListResult {
+/// Like `du -d <depth>`: for every prefix at `depth`, adds up the objects beneath it (at
+/// any depth), and returns one [`DiskUsage`] per prefix, sorted by prefix.
+///
+/// The prefixes at `depth` are the same prefixes that [`list_with_depth`] lists. So
+/// `depth = 0` gives one `DiskUsage` for the whole of `prefix`. Note that this lists
+/// *every* prefix beneath `prefix`, however deep.
+pub async fn disk_usage(
+    store: Arc<dyn ObjectStore>,
+    prefix: Option<&Path>,
+    depth: usize,
+) -> Result<Vec<DiskUsage>> {
+    disk_usage_opts(store, prefix, depth, ListOptions::default()).await
+}
+
+/// Like [`disk_usage`] but with [`ListOptions`].
+pub async fn disk_usage_opts(
+    store: Arc<dyn ObjectStore>,
+    prefix: Option<&Path>,
+    depth: usize,
+    options: ListOptions,
+) -> Result<Vec<DiskUsage>> {
+    let n_parts_of_prefix = prefix.map_or(0, |prefix| prefix.parts().count());
+    let mut usage: HashMap<Path, DiskUsage> = HashMap::new();
+    let mut stream =
+        traverse::spawn_traversal(store, prefix, depth..=usize::MAX, None, false, options);
+    while let Some(prefix_listing) = stream.next().await {
+        let PrefixListing {
+            prefix,
+            list_result,
+            ..
+        } = prefix_listing?;
+        // Every level of the traversal adds one part to the path, so this is the
+        // ancestor of `prefix` at `depth` (or `prefix` itself).
+        let ancestor: Path = prefix.parts().take(n_parts_of_prefix + depth).collect();
+        let disk_usage = usage
+            .entry(ancestor.clone())
+            .or_insert_with(|| DiskUsage::new(ancestor));
+        for object_meta in &list_result.objects {
+            disk_usage.add(object_meta);
+        }
+    }
+    let mut usage: Vec<DiskUsage> = usage.into_values().collect();
+    usage.sort_unstable_by(|a, b| a.prefix.cmp(&b.prefix));
+    Ok(usage)
+}
+
+/// Lists every object whose depth is within `depths`, in a single traversal.
+///
+/// This is similar to `find -mindepth <start> -maxdepth <end>`. Unlike calling
+/// [`list_with_depth`] once per depth, the upper levels are only listed once.
+///
+/// The returned [`ListResult`] contains:
+/// - `objects`: every object at every depth within `depths`.
+/// - `common_prefixes`: the "leaf" prefixes, i.e. the common prefixes found at the
+///   end of `depths`, which have not been listed.
+///
+/// Both are sorted by path.
+///
+/// So `list_with_depth_range(store, prefix, n..=n)` is equivalent to
+/// `list_with_depth(store, prefix, n)`. If `depths` is empty then the returned
+/// [`ListResult`] is empty, and no requests are made.
+///
+/// Using the example bucket from [`list_with_depth`], `depths = 0..=1` returns
+/// `objects=["a.txt", "foo/b.txt"]` and `common_prefixes=["foo/bar"]`.
+pub async fn list_with_depth_range(
+    store: Arc<dyn ObjectStore>,
+    prefix: Option<&Path>,
+    depths: RangeInclusive<usize>,
+) -> Result<ListResult> {
+    list_with_depth_range_opts(store, prefix, depths, ListOptions::default()).await
+}
+
+/// Like [`list_with_depth_range`] but with [`ListOptions`].
+pub async fn list_with_depth_range_opts(
+    store: Arc<dyn ObjectStore>,
+    prefix: Option<&Path>,
+    depths: RangeInclusive<usize>,
+    options: ListOptions,
+) -> Result<ListResult> {
+    let mut combined = ListResult {
+        objects: vec![],
+        common_prefixes: vec![],
+    };
+    if depths.is_empty() {
+        return Ok(combined);
+    }
+    let max_depth = *depths.end();
+    let mut stream = traverse::spawn_traversal(store, prefix, depths, None, false, options);
+    while let Some(prefix_listing) = stream.next().await {
+        let PrefixListing {
+            depth, list_result, ..
+        } = prefix_listing?;
+        combined.objects.extend(list_result.objects);
+        if depth == max_depth {
+            combined.common_prefixes.extend(list_result.common_prefixes);
+        }
+    }
+    sort_list_result(&mut combined);
+    Ok(combined)
+}
+
+/// Like [`list_with_depth`] but, instead of stopping at the first error, carries on listing
+/// the rest of the tree and returns the failures alongside everything that was listed
+/// successfully.
+///
+/// This is useful for buckets where some prefixes can't be listed, e.g. because of
+/// permissions. It's also how to get partial results when [`ListOptions::deadline`]
+/// passes. If the prefix that the traversal starts from can't be listed then the
+/// [`ListResult`] will be empty, and there'll be one failure.
+pub async fn list_with_depth_partial(
+    store: Arc<dyn ObjectStore>,
+    prefix: Option<&Path>,
+    depth: usize,
+) -> PartialListResult {
+    list_with_depth_partial_opts(store, prefix, depth, ListOptions::default()).await
+}
+
+/// Like [`list_with_depth_partial`] but with [`ListOptions`].
+pub async fn list_with_depth_partial_opts(
+    store: Arc<dyn ObjectStore>,
+    prefix: Option<&Path>,
+    depth: usize,
+    options: ListOptions,
+) -> PartialListResult {
+    let mut partial = PartialListResult {
+        list_result: ListResult {
             objects: vec![],
             common_prefixes: vec![],
-        };
-        while let Some(handle) = set.join_next().await {
-            let list_res = handle??;
-            combined.objects.extend(list_res.objects);
-            combined.common_prefixes.extend(list_res.common_prefixes);
+        },
+        failures: vec![],
+        other_errors: vec![],
+        unexplored: vec![],
+    };
+    let mut stream = traverse::spawn_traversal(store, prefix, depth..=depth, None, true, options);
+    while let Some(prefix_listing) = stream.next().await {
+        match prefix_listing {
+            Ok(PrefixListing { list_result, .. }) => {
+                partial.list_result.objects.extend(list_result.objects);
+                partial
+                    .list_result
+                    .common_prefixes
+                    .extend(list_result.common_prefixes);
+            }
+            Err(Error::DeadlineExceeded { path, depth, .. }) => {
+                partial.unexplored.push((path, depth))
+            }
+            Err(e) => match e.path() {
+                Some(path) => partial.failures.push((path.clone(), e)),
+                None => partial.other_errors.push(e),
+            },
         }
-        Ok(combined)
-    })
+    }
+    sort_list_result(&mut partial.list_result);
+    partial.failures.sort_by(|a, b| a.0.cmp(&b.0));
+    partial.unexplored.sort();
+    partial
+}
+
+/// The result of [`list_with_depth_partial`].
+#[derive(Debug)]
+pub struct PartialListResult {
+    /// Everything that was listed successfully, sorted by path.
+    pub list_result: ListResult,
+    /// The prefixes which couldn't be listed (and hence nothing beneath them was listed),
+    /// sorted by path.
+    pub failures: Vec<(Path, Error)>,
+    /// Errors which don't relate to a single prefix, such as [`Error::Join`] if a task
+    /// panicked. Parts of the tree may be missing, but there's no telling which.
+    pub other_errors: Vec<Error>,
+    /// The prefixes (and their depths) which weren't listed because the
+    /// [deadline](ListOptions::deadline) passed, sorted by path.
+    pub unexplored: Vec<(Path, usize)>,
+}
+
+impl PartialListResult {
+    /// Returns `true` if every prefix was listed successfully.
+    pub fn is_complete(&self) -> bool {
+        self.failures.is_empty() && self.other_errors.is_empty() && self.unexplored.is_empty()
+    }
+
+    /// Converts this into a [`Checkpoint`], from which the prefixes which failed or
+    /// weren't listed can be retried with [`resume_list_with_depth`]. `prefix` and `depth`
+    /// must be the same as those passed to [`list_with_depth_partial`].
+    ///
+    /// [`other_errors`](Self::other_errors) don't relate to a single prefix, so can't be
+    /// resumed, and are dropped.
+    pub fn into_checkpoint(self, prefix: Option<&Path>, depth: usize) -> Checkpoint {
+        let mut checkpoint = Checkpoint::new(prefix, depth);
+        checkpoint.pending = self
+            .failures
+            .iter()
+            .filter_map(|(_, e)| Some((e.path()?.clone(), e.depth()?)))
+            .chain(self.unexplored)
+            .collect();
+        checkpoint.list_result = self.list_result;
+        checkpoint
+    }
+}
+
+/// Like [`list_with_depth`] but, instead of waiting for the whole traversal to finish,
+/// returns a [`Stream`](futures::Stream) which yields one [`PrefixListing`] as soon as
+/// each prefix at the target `depth` has been listed.
+///
+/// The order of the items depends on which requests to the object store finish first,
+/// so (unlike [`list_with_depth`]) the results are not sorted.
+/// The stream ends after the first error.
+///
+/// The traversal runs on a spawned Tokio task, so this function must be called from
+/// within a Tokio runtime. Dropping the returned [`ListStream`] cancels the traversal.
+pub fn list_with_depth_stream(
+    store: Arc<dyn ObjectStore>,
+    prefix: Option<&Path>,
+    depth: usize,
+) -> ListStream {
+    list_with_depth_stream_opts(store, prefix, depth, ListOptions::default())
+}
+
+/// Like [`list_with_depth_stream`] but with [`ListOptions`].
+pub fn list_with_depth_stream_opts(
+    store: Arc<dyn ObjectStore>,
+    prefix: Option<&Path>,
+    depth: usize,
+    options: ListOptions,
+) -> ListStream {
+    traverse::spawn_traversal(store, prefix, depth..=depth, None, false, options)
+}
+
+/// Lists the objects and common prefixes which match the glob `pattern`, such as
+/// `data/*/2024-*/*.nc`. See [`Glob`] for the supported syntax.
+///
+/// This is similar to `ls data/*/2024-*/*.nc` at a Unix command line. Each segment of
+/// the pattern is matched against one level of the store, and common prefixes which
+/// don't match are never listed. Listing starts at the longest literal prefix of the
+/// pattern (`data` in the example above).
+///
+/// A `**` segment matches any number of levels, like `ls data/**/*.nc` with Bash's
+/// `globstar` option. The traversal still uses `list_with_delimiter` at every level,
+/// so subtrees which can't match the rest of the pattern are pruned.
+///
+/// The returned [`ListResult`] contains the objects which match `pattern`, and the
+/// common prefixes which match `pattern` (i.e. "directories" at the last level),
+/// sorted by path.
+///
+/// Returns [`Error::InvalidGlob`] if `pattern` is not a valid [`Glob`].
+pub async fn list_glob(store: Arc<dyn ObjectStore>, pattern: &str) -> Result<ListResult> {
+    list_glob_opts(store, pattern, ListOptions::default()).await
+}
+
+/// Like [`list_glob`] but with [`ListOptions`].
+pub async fn list_glob_opts(
+    store: Arc<dyn ObjectStore>,
+    pattern: &str,
+    options: ListOptions,
+) -> Result<ListResult> {
+    let glob = Arc::new(Glob::new(pattern)?);
+    let prefix = glob.literal_prefix();
+    let depths = 0..=glob.max_depth().unwrap_or(usize::MAX);
+    let mut stream = traverse::spawn_traversal(
+        store,
+        prefix.as_ref(),
+        depths,
+        Some(glob.clone()),
+        false,
+        options,
+    );
+    let mut combined = ListResult {
+        objects: vec![],
+        common_prefixes: vec![],
+    };
+    while let Some(prefix_listing) = stream.next().await {
+        let list_result = prefix_listing?.list_result;
+        combined.objects.extend(
+            list_result
+                .objects
+                .into_iter()
+                .filter(|object_meta| glob.matches(&object_meta.location)),
+        );
+        combined.common_prefixes.extend(
+            list_result
+                .common_prefixes
+                .into_iter()
+                .filter(|common_prefix| glob.matches(common_prefix)),
+        );
+    }
+    sort_list_result(&mut combined);
+    Ok(combined)
+}
+
+/// Recursively lists `prefix`, consulting `visitor` at every level to decide which
+/// common prefixes to descend into. See [`ListVisitor`] for details.
+///
+/// There's no depth limit: the traversal continues until the `visitor` declines to
+/// descend any further, or there are no more common prefixes. Results are passed to the
+/// `visitor` rather than returned.
+pub async fn list_with_visitor(
+    store: Arc<dyn ObjectStore>,
+    prefix: Option<&Path>,
+    visitor: Arc<dyn ListVisitor>,
+) -> Result<()> {
+    list_with_visitor_opts(store, prefix, visitor, ListOptions::default()).await
+}
+
+/// Like [`list_with_visitor`] but with [`ListOptions`].
+pub async fn list_with_visitor_opts(
+    store: Arc<dyn ObjectStore>,
+    prefix: Option<&Path>,
+    visitor: Arc<dyn ListVisitor>,
+    options: ListOptions,
+) -> Result<()> {
+    // The visitor sees every listing, so we only need the stream to find out when
+    // the traversal has finished (or failed).
+    let mut stream =
+        traverse::spawn_traversal(store, prefix, 0..=usize::MAX, Some(visitor), false, options);
+    while let Some(prefix_listing) = stream.next().await {
+        prefix_listing?;
+    }
+    Ok(())
 }
 
+/// Sorts `objects` and `common_prefixes` by path, so results don't depend on the order
+/// in which requests complete.
+fn sort_list_result(list_result: &mut ListResult) {
+    list_result
+        .objects
+        .sort_unstable_by(|a, b| a.location.cmp(&b.location));
+    list_result.common_prefixes.sort_unstable();
+}
+
+#[cfg(test)]
+mod test_utils;
+
 #[cfg(test)]
 mod tests {
-    use object_store::{memory::InMemory, PutPayload};
+    use std::{
+        collections::BTreeMap,
+        sync::{atomic::Ordering, Mutex},
+        time::Duration,
+    };
 
-    use super::*;
+    use async_trait::async_trait;
+    use object_store::{memory::InMemory, ObjectMeta, PutPayload};
 
-    async fn create_in_memory_store() -> object_store::Result<InMemory> {
-        const KEYS: [&str; 6] = [
-            "a.txt",
-            "foo/b.txt",
-            "foo/bar/c.txt",
-            "foo/bar/d.txt",
-            "foo/baz/e.txt",
-            "foo/baz/bleh/f.txt",
-        ];
-        let store = InMemory::new();
-        for key in KEYS {
-            store.put(&key.into(), PutPayload::new()).await?;
-        }
-        Ok(store)
-    }
+    use super::*;
+    use crate::test_utils::{create_in_memory_store, MockStore};
 
     /// Returns (object_paths, common_prefixes).
     async fn test_depth_n(depth: usize) -> object_store::Result<(Vec<Path>, Vec<Path>)> {
@@ -129,4 +653,1030 @@ mod tests {
         assert_eq!(common_prefixes, vec![Path::from("foo/baz/bleh")]);
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_stream_depth_1() -> object_store::Result<()> {
+        let store = Arc::new(create_in_memory_store().await?);
+        let mut prefix_listings: Vec<PrefixListing> = list_with_depth_stream(store, None, 1)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<_>>()?;
+        prefix_listings.sort_by(|a, b| a.prefix.cmp(&b.prefix));
+        assert_eq!(prefix_listings.len(), 1);
+        assert_eq!(prefix_listings[0].prefix, Path::from("foo"));
+        assert_eq!(prefix_listings[0].depth, 1);
+        assert_eq!(
+            prefix_listings[0].list_result.common_prefixes,
+            vec![Path::from("foo/bar"), Path::from("foo/baz")]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stream_depth_2_is_tagged_by_prefix() -> object_store::Result<()> {
+        let store = Arc::new(create_in_memory_store().await?);
+        let mut stream = list_with_depth_stream(store, None, 2);
+        let mut prefixes = vec![];
+        while let Some(prefix_listing) = stream.next().await {
+            let PrefixListing {
+                prefix,
+                depth,
+                list_result,
+            } = prefix_listing?;
+            assert_eq!(depth, 2);
+            for object_meta in list_result.objects {
+                assert!(object_meta.location.prefix_matches(&prefix));
+            }
+            prefixes.push(prefix);
+        }
+        prefixes.sort();
+        assert_eq!(prefixes, vec![Path::from("foo/bar"), Path::from("foo/baz")]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_max_concurrency() -> object_store::Result<()> {
+        let store = Arc::new(MockStore::with_n_prefixes(50, Duration::from_millis(5)).await?);
+        let options = ListOptions {
+            max_concurrency: Some(4),
+            ..Default::default()
+        };
+        let ListResult { objects, .. } =
+            list_with_depth_opts(store.clone(), None, 1, options).await?;
+        assert_eq!(objects.len(), 50);
+        assert_eq!(store.max_in_flight.load(Ordering::SeqCst), 4);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_max_concurrency_across_levels() -> object_store::Result<()> {
+        let store = Arc::new(MockStore::new(
+            create_in_memory_store().await?,
+            Duration::from_millis(5),
+        ));
+        let options = ListOptions {
+            max_concurrency: Some(1),
+            ..Default::default()
+        };
+        let ListResult { objects, .. } =
+            list_with_depth_opts(store.clone(), None, 2, options).await?;
+        assert_eq!(objects.len(), 3);
+        assert_eq!(store.max_in_flight.load(Ordering::SeqCst), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_depth_range() -> object_store::Result<()> {
+        let store = Arc::new(create_in_memory_store().await?);
+        let ListResult {
+            objects,
+            common_prefixes,
+        } = list_with_depth_range(store, None, 0..=2).await?;
+        let mut object_paths: Vec<Path> = objects
+            .into_iter()
+            .map(|object_meta| object_meta.location)
+            .collect();
+        object_paths.sort();
+        assert_eq!(
+            object_paths,
+            vec![
+                Path::from("a.txt"),
+                Path::from("foo/b.txt"),
+                Path::from("foo/bar/c.txt"),
+                Path::from("foo/bar/d.txt"),
+                Path::from("foo/baz/e.txt"),
+            ]
+        );
+        assert_eq!(common_prefixes, vec![Path::from("foo/baz/bleh")]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_depth_range_skips_shallow_objects() -> object_store::Result<()> {
+        let store = Arc::new(MockStore::new(
+            create_in_memory_store().await?,
+            Duration::ZERO,
+        ));
+        let ListResult {
+            objects,
+            common_prefixes,
+        } = list_with_depth_range(store.clone(), None, 1..=3).await?;
+        let mut object_paths: Vec<Path> = objects
+            .into_iter()
+            .map(|object_meta| object_meta.location)
+            .collect();
+        object_paths.sort();
+        assert_eq!(
+            object_paths,
+            vec![
+                Path::from("foo/b.txt"),
+                Path::from("foo/bar/c.txt"),
+                Path::from("foo/bar/d.txt"),
+                Path::from("foo/baz/bleh/f.txt"),
+                Path::from("foo/baz/e.txt"),
+            ]
+        );
+        assert!(common_prefixes.is_empty());
+        // One request per prefix: "", "foo", "foo/bar", "foo/baz", "foo/baz/bleh".
+        assert_eq!(store.list_requests.load(Ordering::SeqCst), 5);
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[allow(clippy::reversed_empty_ranges)]
+    async fn test_empty_depth_range() -> object_store::Result<()> {
+        let store = Arc::new(create_in_memory_store().await?);
+        let ListResult {
+            objects,
+            common_prefixes,
+        } = list_with_depth_range(store, None, 2..=1).await?;
+        assert!(objects.is_empty());
+        assert!(common_prefixes.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_list_glob() -> object_store::Result<()> {
+        let store = Arc::new(MockStore::new(
+            create_in_memory_store().await?,
+            Duration::ZERO,
+        ));
+        let ListResult {
+            objects,
+            common_prefixes,
+        } = list_glob(store.clone(), "foo/*/[c-e].txt").await?;
+        let mut object_paths: Vec<Path> = objects
+            .into_iter()
+            .map(|object_meta| object_meta.location)
+            .collect();
+        object_paths.sort();
+        assert_eq!(
+            object_paths,
+            vec![
+                Path::from("foo/bar/c.txt"),
+                Path::from("foo/bar/d.txt"),
+                Path::from("foo/baz/e.txt"),
+            ]
+        );
+        assert!(common_prefixes.is_empty());
+        // "foo", "foo/bar" and "foo/baz". The root is never listed.
+        assert_eq!(store.list_requests.load(Ordering::SeqCst), 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_list_glob_prunes_prefixes() -> object_store::Result<()> {
+        let store = Arc::new(MockStore::new(
+            create_in_memory_store().await?,
+            Duration::ZERO,
+        ));
+        let ListResult {
+            objects,
+            common_prefixes,
+        } = list_glob(store.clone(), "*/{bar,qux}/*").await?;
+        let mut object_paths: Vec<Path> = objects
+            .into_iter()
+            .map(|object_meta| object_meta.location)
+            .collect();
+        object_paths.sort();
+        assert_eq!(
+            object_paths,
+            vec![Path::from("foo/bar/c.txt"), Path::from("foo/bar/d.txt")]
+        );
+        assert!(common_prefixes.is_empty());
+        // "", "foo" and "foo/bar". "foo/baz" is pruned.
+        assert_eq!(store.list_requests.load(Ordering::SeqCst), 3);
+
+        let ListResult {
+            common_prefixes, ..
+        } = list_glob(store, "foo/b*").await?;
+        assert_eq!(
+            common_prefixes,
+            vec![Path::from("foo/bar"), Path::from("foo/baz")]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_list_glob_invalid_pattern() -> object_store::Result<()> {
+        let store = Arc::new(create_in_memory_store().await?);
+        let result = list_glob(store, "foo/[").await;
+        assert!(matches!(result, Err(Error::InvalidGlob(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_list_glob_globstar() -> object_store::Result<()> {
+        let store = Arc::new(MockStore::new(
+            create_in_memory_store().await?,
+            Duration::ZERO,
+        ));
+        let ListResult { objects, .. } = list_glob(store.clone(), "**/{b,e,f}.txt").await?;
+        let mut object_paths: Vec<Path> = objects
+            .into_iter()
+            .map(|object_meta| object_meta.location)
+            .collect();
+        object_paths.sort();
+        assert_eq!(
+            object_paths,
+            vec![
+                Path::from("foo/b.txt"),
+                Path::from("foo/baz/bleh/f.txt"),
+                Path::from("foo/baz/e.txt"),
+            ]
+        );
+
+        // "foo/bar" can't contain a match, so it shouldn't be listed.
+        store.list_requests.store(0, Ordering::SeqCst);
+        let ListResult { objects, .. } = list_glob(store.clone(), "foo/baz/**/*.txt").await?;
+        assert_eq!(objects.len(), 2);
+        // "foo/baz" and "foo/baz/bleh".
+        assert_eq!(store.list_requests.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    /// Skips `_tmp` prefixes, stops at `zarr.json`, and records every object it sees.
+    #[derive(Default)]
+    struct ZarrVisitor {
+        objects: Mutex<Vec<Path>>,
+    }
+
+    #[async_trait]
+    impl ListVisitor for ZarrVisitor {
+        async fn on_prefix(&self, prefix: &Path, _depth: usize) -> bool {
+            prefix.filename() != Some("_tmp")
+        }
+
+        async fn should_descend(
+            &self,
+            _prefix: &Path,
+            _depth: usize,
+            list_result: &ListResult,
+        ) -> bool {
+            !list_result
+                .objects
+                .iter()
+                .any(|object_meta| object_meta.location.filename() == Some("zarr.json"))
+        }
+
+        async fn on_objects(&self, _prefix: &Path, _depth: usize, objects: &[ObjectMeta]) {
+            let mut seen = self.objects.lock().unwrap();
+            seen.extend(
+                objects
+                    .iter()
+                    .map(|object_meta| object_meta.location.clone()),
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_with_visitor() -> object_store::Result<()> {
+        const KEYS: [&str; 6] = [
+            "README.md",
+            "data/_tmp/partial.bin",
+            "data/array/zarr.json",
+            "data/array/c/0/0",
+            "data/other/x/y.txt",
+            "data/other/x/_tmp/z.txt",
+        ];
+        let inner = InMemory::new();
+        for key in KEYS {
+            inner.put(&key.into(), PutPayload::new()).await?;
+        }
+        let store = Arc::new(MockStore::new(inner, Duration::ZERO));
+        let visitor = Arc::new(ZarrVisitor::default());
+        list_with_visitor(store.clone(), None, visitor.clone()).await?;
+        let mut objects = visitor.objects.lock().unwrap().clone();
+        objects.sort();
+        assert_eq!(
+            objects,
+            vec![
+                Path::from("README.md"),
+                Path::from("data/array/zarr.json"),
+                Path::from("data/other/x/y.txt"),
+            ]
+        );
+        // "", "data", "data/array", "data/other" and "data/other/x".
+        assert_eq!(store.list_requests.load(Ordering::SeqCst), 5);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_output_is_sorted() -> object_store::Result<()> {
+        // Make the first prefixes the slowest, so they finish last.
+        let mut store = MockStore::with_n_prefixes(20, Duration::ZERO).await?;
+        for i in 0..20 {
+            let delay = Duration::from_millis(2 * (20 - i));
+            store = store.with_prefix_delay(Path::from(format!("{i:04}")), delay);
+        }
+        let store = Arc::new(store);
+        let ListResult { objects, .. } = list_with_depth(store.clone(), None, 1).await?;
+        let object_paths: Vec<Path> = objects
+            .into_iter()
+            .map(|object_meta| object_meta.location)
+            .collect();
+        let expected: Vec<Path> = (0..20)
+            .map(|i| Path::from(format!("{i:04}/data.bin")))
+            .collect();
+        assert_eq!(object_paths, expected);
+
+        let ListResult { objects, .. } = list_glob(store, "*/*.bin").await?;
+        assert!(objects.is_sorted_by(|a, b| a.location <= b.location));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_error_context() -> object_store::Result<()> {
+        let store = MockStore::new(create_in_memory_store().await?, Duration::ZERO)
+            .with_prefix_error(Path::from("foo/baz/bleh"), usize::MAX);
+        let store = Arc::new(store);
+        let err = list_with_depth(store, None, 3).await.unwrap_err();
+        let Error::List {
+            path,
+            depth,
+            parents,
+            source,
+        } = &err
+        else {
+            panic!("unexpected error: {err}");
+        };
+        assert_eq!(path, &Path::from("foo/baz/bleh"));
+        assert_eq!(*depth, 3);
+        assert_eq!(parents, &vec![Path::from("foo"), Path::from("foo/baz")]);
+        assert!(matches!(source, object_store::Error::Generic { .. }));
+        assert_eq!(err.path(), Some(&Path::from("foo/baz/bleh")));
+        assert!(err.to_string().starts_with(
+            r#"failed to list prefix "foo/baz/bleh" at depth 3 (via ["foo", "foo/baz"])"#
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_error_context_for_starting_prefix() -> object_store::Result<()> {
+        let store = MockStore::new(create_in_memory_store().await?, Duration::ZERO)
+            .with_prefix_error(Path::from("foo"), usize::MAX);
+        let store = Arc::new(store);
+        let err = list_with_depth(store, Some(&Path::from("foo")), 1)
+            .await
+            .unwrap_err();
+        assert_eq!(err.path(), Some(&Path::from("foo")));
+        assert_eq!(err.depth(), Some(0));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_partial_results() -> object_store::Result<()> {
+        let store = MockStore::new(create_in_memory_store().await?, Duration::ZERO)
+            .with_prefix_error(Path::from("foo/bar"), usize::MAX);
+        let store = Arc::new(store);
+
+        // Without partial results, the whole listing fails:
+        assert!(list_with_depth(store.clone(), None, 2).await.is_err());
+
+        let partial = list_with_depth_partial(store, None, 2).await;
+        assert!(!partial.is_complete());
+        let object_paths: Vec<&Path> = partial
+            .list_result
+            .objects
+            .iter()
+            .map(|object_meta| &object_meta.location)
+            .collect();
+        assert_eq!(object_paths, vec![&Path::from("foo/baz/e.txt")]);
+        assert_eq!(
+            partial.list_result.common_prefixes,
+            vec![Path::from("foo/baz/bleh")]
+        );
+        assert_eq!(partial.failures.len(), 1);
+        let (path, err) = &partial.failures[0];
+        assert_eq!(path, &Path::from("foo/bar"));
+        assert_eq!(err.depth(), Some(2));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_partial_results_root_failure() -> object_store::Result<()> {
+        let store = MockStore::new(create_in_memory_store().await?, Duration::ZERO)
+            .with_prefix_error(Path::from("foo"), usize::MAX);
+        let store = Arc::new(store);
+        let partial = list_with_depth_partial(store, Some(&Path::from("foo")), 1).await;
+        assert!(partial.list_result.objects.is_empty());
+        assert_eq!(partial.failures.len(), 1);
+        assert_eq!(partial.failures[0].0, Path::from("foo"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_partial_results_other_errors() -> object_store::Result<()> {
+        let store = MockStore::new(create_in_memory_store().await?, Duration::ZERO)
+            .with_prefix_panic(Path::from("foo/bar"));
+        let partial = list_with_depth_partial(Arc::new(store), None, 2).await;
+        assert!(!partial.is_complete());
+        // The panic isn't attributed to a prefix, least of all the root.
+        assert!(partial.failures.is_empty());
+        assert_eq!(partial.other_errors.len(), 1);
+        assert!(matches!(partial.other_errors[0], Error::Join(_)));
+        assert_eq!(partial.list_result.objects.len(), 1);
+        Ok(())
+    }
+
+    async fn store_failing_twice() -> object_store::Result<Arc<MockStore>> {
+        let store = MockStore::new(create_in_memory_store().await?, Duration::ZERO)
+            .with_prefix_error(Path::from("foo/bar"), 2);
+        Ok(Arc::new(store))
+    }
+
+    #[tokio::test]
+    async fn test_retry() -> object_store::Result<()> {
+        let retry = RetryConfig {
+            initial_backoff: Duration::from_millis(1),
+            ..Default::default()
+        };
+
+        // Two attempts aren't enough:
+        let options = ListOptions {
+            retry: Some(RetryConfig {
+                max_attempts: 2,
+                ..retry.clone()
+            }),
+            ..Default::default()
+        };
+        let err = list_with_depth_opts(store_failing_twice().await?, None, 2, options)
+            .await
+            .unwrap_err();
+        assert_eq!(err.path(), Some(&Path::from("foo/bar")));
+
+        // Three attempts are enough:
+        let store = store_failing_twice().await?;
+        let options = ListOptions {
+            retry: Some(retry),
+            ..Default::default()
+        };
+        let ListResult { objects, .. } =
+            list_with_depth_opts(store.clone(), None, 2, options).await?;
+        assert_eq!(objects.len(), 3);
+        // "", "foo", "foo/bar" (three times) and "foo/baz".
+        assert_eq!(store.list_requests.load(Ordering::SeqCst), 6);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_adaptive_concurrency() -> object_store::Result<()> {
+        let store = Arc::new(MockStore::with_n_prefixes(50, Duration::from_millis(5)).await?);
+        let options = ListOptions {
+            adaptive_concurrency: Some(AdaptiveConcurrency {
+                initial: 3,
+                max: 5,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let ListResult { objects, .. } =
+            list_with_depth_opts(store.clone(), None, 1, options).await?;
+        assert_eq!(objects.len(), 50);
+        let max_in_flight = store.max_in_flight.load(Ordering::SeqCst);
+        assert!((3..=5).contains(&max_in_flight), "{max_in_flight}");
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limiter_is_shared_between_listings() -> object_store::Result<()> {
+        let store = Arc::new(MockStore::with_n_prefixes(10, Duration::ZERO).await?);
+        let options = ListOptions {
+            rate_limiter: Some(Arc::new(RateLimiter::new(10.0, 1))),
+            ..Default::default()
+        };
+        let start = tokio::time::Instant::now();
+        let (a, b) = tokio::join!(
+            list_with_depth_opts(store.clone(), None, 1, options.clone()),
+            list_with_depth_opts(store.clone(), None, 1, options),
+        );
+        assert_eq!(a?.objects.len(), 10);
+        assert_eq!(b?.objects.len(), 10);
+        // 22 requests: the first is free, then 10 per second.
+        assert_eq!(store.list_requests.load(Ordering::SeqCst), 22);
+        assert_eq!(start.elapsed().as_millis(), 2100);
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limiter_after_deadline() -> object_store::Result<()> {
+        let store = Arc::new(MockStore::with_n_prefixes(1000, Duration::ZERO).await?);
+        let rate_limiter = Arc::new(RateLimiter::new(10.0, 1));
+        let options = ListOptions {
+            rate_limiter: Some(rate_limiter.clone()),
+            deadline: Some(tokio::time::Instant::now() + Duration::from_secs(1)),
+            ..Default::default()
+        };
+        let err = list_with_depth_opts(store.clone(), None, 1, options)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::DeadlineExceeded { .. }), "{err}");
+
+        // The requests which were waiting when the deadline passed have given their
+        // tokens back, so another listing sharing the rate limiter isn't held up.
+        let options = ListOptions {
+            rate_limiter: Some(rate_limiter),
+            ..Default::default()
+        };
+        let start = tokio::time::Instant::now();
+        list_with_depth_opts(store, None, 0, options).await?;
+        assert!(
+            start.elapsed() <= Duration::from_millis(100),
+            "{:?}",
+            start.elapsed()
+        );
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limiter_with_max_concurrency() -> object_store::Result<()> {
+        let store = MockStore::with_n_prefixes(40, Duration::ZERO)
+            .await?
+            .with_prefix_delay(Path::from("0001"), Duration::from_secs(5));
+        let store = Arc::new(store);
+        let options = ListOptions {
+            rate_limiter: Some(Arc::new(RateLimiter::new(10.0, 1))),
+            max_concurrency: Some(1),
+            ..Default::default()
+        };
+        let ListResult { objects, .. } =
+            list_with_depth_opts(store.clone(), None, 1, options).await?;
+        assert_eq!(objects.len(), 40);
+
+        // The requests which queued up behind the slow prefix mustn't burst through when
+        // it finishes: they're still spaced out by the rate limit.
+        let request_starts = store.request_starts.lock().unwrap();
+        assert_eq!(request_starts.len(), 41);
+        for pair in request_starts.windows(2) {
+            let gap = pair[1] - pair[0];
+            assert!(gap >= Duration::from_millis(100), "{gap:?}");
+        }
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_deadline() -> object_store::Result<()> {
+        let store = MockStore::new(create_in_memory_store().await?, Duration::from_millis(10))
+            .with_prefix_delay(Path::from("foo/baz"), Duration::from_secs(3600));
+        let store = Arc::new(store);
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(1);
+        let options = ListOptions {
+            deadline: Some(deadline),
+            ..Default::default()
+        };
+
+        let err = list_with_depth_opts(store.clone(), None, 3, options.clone())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::DeadlineExceeded { .. }), "{err}");
+        assert_eq!(tokio::time::Instant::now(), deadline);
+
+        let options = ListOptions {
+            deadline: Some(tokio::time::Instant::now() + Duration::from_secs(1)),
+            ..Default::default()
+        };
+        let partial = list_with_depth_partial_opts(store, None, 2, options).await;
+        assert!(!partial.is_complete());
+        assert!(partial.failures.is_empty());
+        let object_paths: Vec<&Path> = partial
+            .list_result
+            .objects
+            .iter()
+            .map(|object_meta| &object_meta.location)
+            .collect();
+        assert_eq!(
+            object_paths,
+            vec![&Path::from("foo/bar/c.txt"), &Path::from("foo/bar/d.txt")]
+        );
+        assert_eq!(partial.unexplored, vec![(Path::from("foo/baz"), 2)]);
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_deadline_stops_spawning() -> object_store::Result<()> {
+        let store = Arc::new(MockStore::with_n_prefixes(3, Duration::from_secs(10)).await?);
+        let options = ListOptions {
+            deadline: Some(tokio::time::Instant::now() + Duration::from_secs(15)),
+            ..Default::default()
+        };
+        // The root takes 10 seconds, and the three prefixes would be done at 20 seconds.
+        let partial = list_with_depth_partial_opts(store.clone(), None, 2, options).await;
+        assert!(partial.list_result.objects.is_empty());
+        assert_eq!(partial.unexplored.len(), 3);
+        assert_eq!(store.list_requests.load(Ordering::SeqCst), 4);
+
+        // Now the deadline passes after the prefixes have been listed, but before
+        // anything at depth 2 could be spawned.
+        store.list_requests.store(0, Ordering::SeqCst);
+        let options = ListOptions {
+            deadline: Some(tokio::time::Instant::now() + Duration::from_secs(20)),
+            ..Default::default()
+        };
+        let partial = list_with_depth_partial_opts(store.clone(), None, 2, options).await;
+        assert!(partial.unexplored.is_empty());
+        assert_eq!(store.list_requests.load(Ordering::SeqCst), 4);
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_request_timeout() -> object_store::Result<()> {
+        let store = MockStore::new(create_in_memory_store().await?, Duration::ZERO)
+            .with_prefix_delay(Path::from("foo/baz"), Duration::from_secs(3600));
+        let store = Arc::new(store);
+        let options = ListOptions {
+            request_timeout: Some(Duration::from_secs(1)),
+            retry: Some(RetryConfig::default()),
+            ..Default::default()
+        };
+        let partial = list_with_depth_partial_opts(store.clone(), None, 2, options).await;
+        assert_eq!(partial.list_result.objects.len(), 2);
+        assert_eq!(partial.failures.len(), 1);
+        let (path, err) = &partial.failures[0];
+        assert_eq!(path, &Path::from("foo/baz"));
+        assert!(err.to_string().contains("timed out"), "{err}");
+        // "", "foo", "foo/bar" and three attempts at "foo/baz".
+        assert_eq!(store.list_requests.load(Ordering::SeqCst), 6);
+        Ok(())
+    }
+
+    /// Returns the (object_paths, common_prefixes) from `list_with_depth(store, None, 2)`.
+    fn expected_depth_2() -> (Vec<Path>, Vec<Path>) {
+        let object_paths = ["foo/bar/c.txt", "foo/bar/d.txt", "foo/baz/e.txt"];
+        (
+            object_paths.into_iter().map(Path::from).collect(),
+            vec![Path::from("foo/baz/bleh")],
+        )
+    }
+
+    fn paths(list_result: ListResult) -> (Vec<Path>, Vec<Path>) {
+        let object_paths = list_result
+            .objects
+            .into_iter()
+            .map(|object_meta| object_meta.location)
+            .collect();
+        (object_paths, list_result.common_prefixes)
+    }
+
+    #[tokio::test]
+    async fn test_resume_from_partial() -> object_store::Result<()> {
+        let store = MockStore::new(create_in_memory_store().await?, Duration::ZERO)
+            .with_prefix_error(Path::from("foo/bar"), 1);
+        let store = Arc::new(store);
+        let partial = list_with_depth_partial(store.clone(), None, 2).await;
+        assert!(!partial.is_complete());
+        let checkpoint = partial.into_checkpoint(None, 2);
+        assert_eq!(
+            checkpoint.pending,
+            BTreeMap::from([(Path::from("foo/bar"), 2)])
+        );
+
+        store.list_requests.store(0, Ordering::SeqCst);
+        let list_result = resume_list_with_depth(store.clone(), checkpoint).await?;
+        assert_eq!(store.list_requests.load(Ordering::SeqCst), 1);
+        assert_eq!(paths(list_result), expected_depth_2());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_resume_from_new_checkpoint() -> object_store::Result<()> {
+        let store = Arc::new(create_in_memory_store().await?);
+        let checkpoint = Checkpoint::new(Some(&Path::from("foo")), 1);
+        let list_result = resume_list_with_depth(store.clone(), checkpoint).await?;
+        let expected = list_with_depth(store, Some(&Path::from("foo")), 1).await?;
+        assert_eq!(list_result.objects, expected.objects);
+        assert_eq!(list_result.common_prefixes, expected.common_prefixes);
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[tokio::test]
+    async fn test_checkpoint_file() -> object_store::Result<()> {
+        let path = std::env::temp_dir().join(format!(
+            "list_with_depth_test_checkpoint_{}.json",
+            std::process::id()
+        ));
+        let store = MockStore::new(create_in_memory_store().await?, Duration::ZERO)
+            .with_prefix_error(Path::from("foo/baz"), 1);
+        let store = Arc::new(store);
+        let options = ListOptions {
+            checkpoint_file: Some(CheckpointFile::new(&path)),
+            ..Default::default()
+        };
+        assert!(
+            list_with_depth_opts(store.clone(), None, 2, options.clone())
+                .await
+                .is_err()
+        );
+
+        // The checkpoint is written when the listing fails.
+        let checkpoint = Checkpoint::load(&path).unwrap();
+        assert!(checkpoint.pending.contains_key(&Path::from("foo/baz")));
+        assert!(!checkpoint.pending.contains_key(&Path::from("foo")));
+
+        // Starting a new listing would overwrite the unfinished checkpoint, so it's refused.
+        let err = list_with_depth_opts(store.clone(), None, 2, options.clone())
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, Error::Checkpoint(e) if e.kind() == std::io::ErrorKind::AlreadyExists),
+            "{err}"
+        );
+        let unchanged = Checkpoint::load(&path).unwrap();
+        assert!(unchanged.pending.contains_key(&Path::from("foo/baz")));
+
+        let list_result =
+            resume_list_with_depth_opts(store.clone(), checkpoint, options.clone()).await?;
+        assert_eq!(paths(list_result), expected_depth_2());
+
+        // ...and when it finishes.
+        let checkpoint = Checkpoint::load(&path).unwrap();
+        assert!(checkpoint.is_complete());
+
+        // A finished checkpoint can be overwritten by a new listing.
+        let list_result = list_with_depth_opts(store, Some(&Path::from("foo")), 1, options).await?;
+        assert_eq!(list_result.objects.len(), 3);
+        let checkpoint = Checkpoint::load(&path).unwrap();
+        assert!(checkpoint.is_complete());
+        assert_eq!(checkpoint.depth, 1);
+        std::fs::remove_file(path).unwrap();
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[tokio::test]
+    async fn test_checkpoint_file_unwritable() -> object_store::Result<()> {
+        let store = MockStore::new(create_in_memory_store().await?, Duration::ZERO)
+            .with_prefix_error(Path::from("foo/baz"), 1);
+        let path = std::env::temp_dir()
+            .join(format!(
+                "list_with_depth_test_missing_{}",
+                std::process::id()
+            ))
+            .join("checkpoint.json");
+        let options = ListOptions {
+            checkpoint_file: Some(CheckpointFile::new(&path)),
+            ..Default::default()
+        };
+        // The listing's error isn't hidden by the failure to write the checkpoint.
+        let err = list_with_depth_opts(Arc::new(store), None, 2, options)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::List { .. }), "{err}");
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_progress() -> object_store::Result<()> {
+        let inner = InMemory::new();
+        inner.put(&"a.bin".into(), vec![0; 10].into()).await?;
+        inner.put(&"foo/b.bin".into(), vec![0; 100].into()).await?;
+        inner
+            .put(&"foo/bar/c.bin".into(), vec![0; 1000].into())
+            .await?;
+        inner
+            .put(&"foo/baz/d/e.bin".into(), vec![0; 1].into())
+            .await?;
+        let store = Arc::new(MockStore::new(inner, Duration::from_millis(10)));
+        let progress = Arc::new(ListProgress::default());
+        let options = ListOptions {
+            progress: Some(progress.clone()),
+            ..Default::default()
+        };
+        let listing = tokio::spawn(list_with_depth_opts(store, None, 2, options));
+
+        // The root listing is in flight.
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        let snapshot = progress.snapshot();
+        assert_eq!(snapshot.prefixes_discovered, 1);
+        assert_eq!(snapshot.prefixes_listed, 0);
+        assert_eq!(snapshot.requests_in_flight, 1);
+
+        listing.await.unwrap()?;
+        assert_eq!(
+            progress.snapshot(),
+            ProgressSnapshot {
+                // "", "foo", "foo/bar" and "foo/baz", but not "foo/baz/d".
+                prefixes_discovered: 4,
+                prefixes_listed: 4,
+                objects_found: 3,
+                bytes_seen: 1110,
+                max_depth: 2,
+                requests_in_flight: 0,
+            }
+        );
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stats() -> object_store::Result<()> {
+        let store = MockStore::new(create_in_memory_store().await?, Duration::from_millis(10))
+            .with_prefix_delay(Path::from("foo/baz"), Duration::from_millis(50))
+            .with_prefix_error(Path::from("foo/bar"), 1);
+        let options = ListOptions {
+            retry: Some(RetryConfig {
+                jitter: false,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let (list_result, stats) =
+            list_with_depth_stats_opts(Arc::new(store), None, 2, options).await?;
+        assert_eq!(list_result.objects.len(), 3);
+        assert_eq!(
+            stats,
+            ListStats {
+                // "", "foo", "foo/bar" (twice) and "foo/baz".
+                requests: 5,
+                retries: 1,
+                prefixes_per_depth: vec![1, 1, 2],
+                slowest_prefix: Some((Path::from("foo/baz"), Duration::from_millis(50))),
+                // 10ms for "", 10ms for "foo", then "foo/bar" fails after 10ms and is
+                // retried after 100ms.
+                elapsed: Duration::from_millis(140),
+            }
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tokio::test]
+    async fn test_tracing_spans() -> object_store::Result<()> {
+        use tracing::{
+            field::{Field, Visit},
+            span,
+        };
+        use tracing_subscriber::{layer::Context, prelude::*, registry::LookupSpan, Layer};
+
+        /// A span's label, and the label of its parent.
+        type Edge = (String, Option<String>);
+
+        /// Records each span as (label, label of parent), where the label is the `prefix`
+        /// field for per-prefix spans, and the span's name otherwise. Also records the
+        /// `error` fields.
+        #[derive(Default, Clone)]
+        struct SpanTree {
+            spans: Arc<Mutex<Vec<Edge>>>,
+            errors: Arc<Mutex<Vec<String>>>,
+        }
+
+        struct Label(String);
+
+        struct FieldVisitor<'a>(&'a str, Option<String>);
+
+        impl Visit for FieldVisitor<'_> {
+            fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                if field.name() == self.0 {
+                    self.1 = Some(format!("{value:?}"));
+                }
+            }
+        }
+
+        impl<S: tracing::Subscriber + for<'a> LookupSpan<'a>> Layer<S> for SpanTree {
+            fn on_new_span(
+                &self,
+                attrs: &span::Attributes<'_>,
+                id: &span::Id,
+                ctx: Context<'_, S>,
+            ) {
+                let mut visitor = FieldVisitor("prefix", None);
+                attrs.record(&mut visitor);
+                let span = ctx.span(id).unwrap();
+                let label = visitor.1.unwrap_or_else(|| span.name().to_string());
+                let parent = span
+                    .parent()
+                    .map(|parent| parent.extensions().get::<Label>().unwrap().0.clone());
+                span.extensions_mut().insert(Label(label.clone()));
+                self.spans.lock().unwrap().push((label, parent));
+            }
+
+            fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+                let mut visitor = FieldVisitor("error", None);
+                values.record(&mut visitor);
+                if visitor.1.is_some() {
+                    let span = ctx.span(id).unwrap();
+                    let label = span.extensions().get::<Label>().unwrap().0.clone();
+                    self.errors.lock().unwrap().push(label);
+                }
+            }
+        }
+
+        let span_tree = SpanTree::default();
+        let _guard = tracing::subscriber::set_default(
+            tracing_subscriber::registry().with(span_tree.clone()),
+        );
+        let store = MockStore::new(create_in_memory_store().await?, Duration::ZERO)
+            .with_prefix_error(Path::from("foo/baz"), 1);
+        let span = tracing::info_span!("caller");
+        let partial = tracing::Instrument::instrument(
+            list_with_depth_partial(Arc::new(store), None, 2),
+            span,
+        )
+        .await;
+        assert_eq!(partial.failures.len(), 1);
+
+        let mut spans = span_tree.spans.lock().unwrap().clone();
+        spans.sort();
+        let label = |label: &str| Some(label.to_string());
+        assert_eq!(
+            spans,
+            vec![
+                ("".to_string(), label("list_with_depth")),
+                ("caller".to_string(), None),
+                ("foo".to_string(), label("")),
+                ("foo/bar".to_string(), label("foo")),
+                ("foo/baz".to_string(), label("foo")),
+                ("list_with_depth".to_string(), label("caller")),
+            ]
+        );
+        assert_eq!(
+            *span_tree.errors.lock().unwrap(),
+            vec!["foo/baz".to_string()]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_list_levels() -> object_store::Result<()> {
+        let store = Arc::new(MockStore::new(
+            create_in_memory_store().await?,
+            Duration::ZERO,
+        ));
+        let levels = list_levels(store.clone(), None, 2).await?;
+        assert_eq!(levels.len(), 3);
+        // "", "foo", "foo/bar" and "foo/baz" are each listed once.
+        assert_eq!(store.list_requests.load(Ordering::SeqCst), 4);
+        for (depth, level) in levels.into_iter().enumerate() {
+            let expected = list_with_depth(store.clone(), None, depth).await?;
+            assert_eq!(paths(level), paths(expected), "depth {depth}");
+        }
+
+        // The tree is only four levels deep, so listing stops there.
+        let mut levels = list_levels(store, None, usize::MAX).await?;
+        assert_eq!(levels.len(), 4);
+        assert_eq!(
+            paths(levels.pop().unwrap()),
+            (vec![Path::from("foo/baz/bleh/f.txt")], vec![])
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_disk_usage() -> object_store::Result<()> {
+        let store = InMemory::new();
+        for (key, size) in [
+            ("a.bin", 1),
+            ("foo/b.bin", 10),
+            ("foo/bar/c.bin", 100),
+            ("foo/bar/deep/er/d.bin", 1000),
+            ("foo/baz/e.bin", 10000),
+            ("qux/f.bin", 100000),
+        ] {
+            store.put(&key.into(), vec![0; size].into()).await?;
+            // Make sure every object has a different `last_modified`.
+            tokio::time::sleep(Duration::from_millis(2)).await;
+        }
+        let store = Arc::new(store);
+        let summarise = |disk_usage: &DiskUsage| {
+            (
+                disk_usage.prefix.to_string(),
+                disk_usage.objects,
+                disk_usage.bytes,
+                disk_usage.newest.as_ref().unwrap().location.to_string(),
+                disk_usage.oldest.as_ref().unwrap().location.to_string(),
+            )
+        };
+
+        let usage = disk_usage(store.clone(), None, 0).await?;
+        assert_eq!(
+            usage.iter().map(summarise).collect::<Vec<_>>(),
+            vec![("".into(), 6, 111111, "qux/f.bin".into(), "a.bin".into())]
+        );
+
+        let usage = disk_usage(store.clone(), Some(&Path::from("foo")), 1).await?;
+        assert_eq!(
+            usage.iter().map(summarise).collect::<Vec<_>>(),
+            vec![
+                (
+                    "foo/bar".into(),
+                    2,
+                    1100,
+                    "foo/bar/deep/er/d.bin".into(),
+                    "foo/bar/c.bin".into()
+                ),
+                (
+                    "foo/baz".into(),
+                    1,
+                    10000,
+                    "foo/baz/e.bin".into(),
+                    "foo/baz/e.bin".into()
+                ),
+            ]
+        );
+
+        // Objects are counted however deep they are beneath the prefix.
+        let usage = disk_usage(store, Some(&Path::from("foo/bar/deep")), 0).await?;
+        assert_eq!(usage[0].objects, 1);
+        Ok(())
+    }
 }
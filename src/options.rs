@@ -0,0 +1,77 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::time::Instant;
+
+#[cfg(feature = "serde")]
+use crate::CheckpointFile;
+use crate::{AdaptiveConcurrency, ListProgress, RateLimiter, RetryConfig};
+
+/// Options for the `_opts` variants of this crate's listing functions, such as
+/// [`list_with_depth_opts`](crate::list_with_depth_opts) and
+/// [`list_with_depth_stream_opts`](crate::list_with_depth_stream_opts).
+///
+/// Use `..Default::default()` to only set the options you care about:
+///
+/// ```
+/// use list_with_depth::ListOptions;
+///
+/// let options = ListOptions {
+///     max_concurrency: Some(32),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ListOptions {
+    /// The maximum number of `list_with_delimiter` requests in flight at any one time,
+    /// across all levels of the traversal. `None` (the default) means no limit.
+    /// `Some(0)` is treated as `Some(1)`.
+    ///
+    /// Without a limit, listing a bucket with tens of thousands of prefixes will fire
+    /// tens of thousands of simultaneous requests, which may be throttled by the
+    /// object store or exhaust the available file descriptors.
+    pub max_concurrency: Option<usize>,
+
+    /// Automatically adjusts the number of `list_with_delimiter` requests in flight,
+    /// backing off when the object store throttles requests. `None` (the default) means
+    /// the number of requests in flight is only limited by `max_concurrency`.
+    pub adaptive_concurrency: Option<AdaptiveConcurrency>,
+
+    /// How to retry listing a prefix after a failure. `None` (the default) means that
+    /// failures are not retried.
+    pub retry: Option<RetryConfig>,
+
+    /// Limits the rate of `list_with_delimiter` requests. Every attempt (including
+    /// retries) counts towards the limit. `None` (the default) means no limit.
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+
+    /// The maximum duration of each `list_with_delimiter` request. A request which takes
+    /// longer fails with an [`object_store::Error::Generic`] (which is retried, if `retry`
+    /// is set). `None` (the default) means no timeout.
+    pub request_timeout: Option<Duration>,
+
+    /// When to give up. Once the deadline has passed, no more prefixes are listed, and
+    /// requests in flight are cancelled. Each prefix which wasn't listed is reported as an
+    /// [`Error::DeadlineExceeded`](crate::Error::DeadlineExceeded). Use
+    /// [`list_with_depth_partial_opts`](crate::list_with_depth_partial_opts) to get
+    /// everything that was listed before the deadline, plus the prefixes which weren't.
+    /// `None` (the default) means no deadline.
+    pub deadline: Option<Instant>,
+
+    /// Periodically writes a [`Checkpoint`](crate::Checkpoint) to a local file, so that a
+    /// long listing can be resumed with
+    /// [`resume_list_with_depth`](crate::resume_list_with_depth) after a crash. The
+    /// checkpoint is also written when the listing fails or finishes. Only used by
+    /// [`list_with_depth_opts`](crate::list_with_depth_opts) and
+    /// [`resume_list_with_depth_opts`](crate::resume_list_with_depth_opts). `None` (the
+    /// default) means no checkpoints are written.
+    ///
+    /// `list_with_depth_opts` refuses to start if the file holds the checkpoint of an
+    /// unfinished listing, so it can't overwrite the progress of a listing which crashed.
+    /// Load that checkpoint and resume from it instead.
+    #[cfg(feature = "serde")]
+    pub checkpoint_file: Option<CheckpointFile>,
+
+    /// Counts the prefixes, objects and requests while the listing runs, e.g. to show a
+    /// progress bar. `None` (the default) means no counting.
+    pub progress: Option<Arc<ListProgress>>,
+}
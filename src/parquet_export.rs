@@ -0,0 +1,140 @@
+use std::{fs::File, io::Write};
+
+use object_store::ObjectMeta;
+use parquet::{
+    arrow::ArrowWriter, basic::Compression, errors::Result, file::properties::WriterProperties,
+};
+
+use crate::{objects_schema, objects_to_record_batch, PrefixListing};
+
+/// Writes the objects from listings to a Parquet file, in the schema given by
+/// [`objects_schema`](crate::objects_schema), compressed with Snappy.
+///
+/// Each listing is converted and handed to the Parquet writer as soon as it's passed in,
+/// so a whole-bucket inventory can be written from
+/// [`list_with_depth_stream`](crate::list_with_depth_stream) without holding it all in
+/// memory (only the current row group is buffered):
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use futures::TryStreamExt;
+/// # use list_with_depth::{list_with_depth_stream, ParquetWriter};
+/// # use object_store::memory::InMemory;
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let store = Arc::new(InMemory::new());
+/// # let path = std::env::temp_dir().join(format!("list_with_depth_doctest_{}.parquet", std::process::id()));
+/// let mut writer = ParquetWriter::create(&path)?;
+/// let mut stream = list_with_depth_stream(store, None, 2);
+/// while let Some(listing) = stream.try_next().await? {
+///     writer.write_listing(&listing)?;
+/// }
+/// writer.close()?;
+/// # std::fs::remove_file(path)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Common prefixes aren't written. The file is only valid once [`close`](Self::close)
+/// has been called.
+pub struct ParquetWriter<W: Write + Send = File> {
+    writer: ArrowWriter<W>,
+}
+
+impl ParquetWriter<File> {
+    /// Creates (or truncates) the file at `path`.
+    pub fn create(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        Self::new(File::create(path)?)
+    }
+}
+
+impl<W: Write + Send> ParquetWriter<W> {
+    /// Writes a Parquet file to `out`.
+    pub fn new(out: W) -> Result<Self> {
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let writer = ArrowWriter::try_new(out, objects_schema(), Some(properties))?;
+        Ok(Self { writer })
+    }
+
+    /// Writes the objects in `listing`.
+    pub fn write_listing(&mut self, listing: &PrefixListing) -> Result<()> {
+        self.write_objects(&listing.list_result.objects, listing.depth)
+    }
+
+    /// Writes `objects`, which were returned by a listing at `depth`.
+    pub fn write_objects(&mut self, objects: &[ObjectMeta], depth: usize) -> Result<()> {
+        if objects.is_empty() {
+            return Ok(());
+        }
+        self.writer.write(&objects_to_record_batch(objects, depth)?)
+    }
+
+    /// Writes the Parquet footer and flushes the file.
+    pub fn close(self) -> Result<()> {
+        self.writer.close()?;
+        Ok(())
+    }
+}
+
+impl<W: Write + Send> std::fmt::Debug for ParquetWriter<W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ParquetWriter")
+            .field("in_progress_rows", &self.writer.in_progress_rows())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{cast::AsArray, types::UInt64Type};
+    use futures::TryStreamExt;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    use crate::{list_with_depth_stream, test_utils::create_in_memory_store};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_parquet_writer() {
+        let path = std::env::temp_dir().join(format!(
+            "list_with_depth_test_{}.parquet",
+            std::process::id()
+        ));
+        let store = Arc::new(create_in_memory_store().await.unwrap());
+        let mut writer = ParquetWriter::create(&path).unwrap();
+        let mut stream = list_with_depth_stream(store, None, 2);
+        while let Some(listing) = stream.try_next().await.unwrap() {
+            writer.write_listing(&listing).unwrap();
+        }
+        writer.close().unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut rows = vec![];
+        for batch in reader {
+            let batch = batch.unwrap();
+            assert_eq!(batch.schema(), objects_schema());
+            let locations = batch.column(0).as_string::<i32>();
+            let depths = batch.column(5).as_primitive::<UInt64Type>();
+            for i in 0..batch.num_rows() {
+                rows.push((locations.value(i).to_string(), depths.value(i)));
+            }
+        }
+        rows.sort();
+        assert_eq!(
+            rows,
+            vec![
+                ("foo/bar/c.txt".to_string(), 2),
+                ("foo/bar/d.txt".to_string(), 2),
+                ("foo/baz/e.txt".to_string(), 2),
+            ]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
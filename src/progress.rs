@@ -0,0 +1,124 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use object_store::ListResult;
+
+/// Live counters for a running listing. Set
+/// [`ListOptions::progress`](crate::ListOptions::progress) to collect them, and call
+/// [`ListProgress::snapshot`] (e.g. from another task, on a timer) to read them.
+///
+/// The counters are only ever incremented (except `requests_in_flight`), so one
+/// `ListProgress` can be shared between several listings to report their combined
+/// progress.
+///
+/// ```
+/// use std::{sync::Arc, time::Duration};
+/// use list_with_depth::{list_with_depth_opts, ListOptions, ListProgress};
+/// use object_store::memory::InMemory;
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> list_with_depth::Result<()> {
+/// let progress = Arc::new(ListProgress::default());
+/// let reporter = tokio::spawn({
+///     let progress = progress.clone();
+///     async move {
+///         loop {
+///             tokio::time::sleep(Duration::from_secs(1)).await;
+///             let snapshot = progress.snapshot();
+///             println!(
+///                 "listed {} of {} prefixes",
+///                 snapshot.prefixes_listed, snapshot.prefixes_discovered
+///             );
+///         }
+///     }
+/// });
+/// let options = ListOptions {
+///     progress: Some(progress),
+///     ..Default::default()
+/// };
+/// list_with_depth_opts(Arc::new(InMemory::new()), None, 2, options).await?;
+/// reporter.abort();
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct ListProgress {
+    prefixes_discovered: AtomicUsize,
+    prefixes_listed: AtomicUsize,
+    objects_found: AtomicUsize,
+    bytes_seen: AtomicU64,
+    max_depth: AtomicUsize,
+    requests_in_flight: AtomicUsize,
+}
+
+/// A point-in-time copy of the counters in a [`ListProgress`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProgressSnapshot {
+    /// The number of prefixes which have been queued for listing, including the prefix
+    /// that the traversal started from. Common prefixes at the deepest level of the
+    /// traversal aren't listed, so they aren't counted.
+    pub prefixes_discovered: usize,
+    /// The number of prefixes which have been listed successfully. Once the listing has
+    /// finished, the difference between this and `prefixes_discovered` is the number of
+    /// prefixes which failed (or weren't listed before the deadline).
+    pub prefixes_listed: usize,
+    /// The number of objects in all the listings so far, at every depth.
+    pub objects_found: usize,
+    /// The total size of `objects_found`, in bytes.
+    pub bytes_seen: u64,
+    /// The deepest depth listed so far.
+    pub max_depth: usize,
+    /// The number of `list_with_delimiter` requests in flight right now.
+    pub requests_in_flight: usize,
+}
+
+impl ListProgress {
+    /// Reads the counters. Each counter is read separately, so the snapshot may mix
+    /// counters from slightly different moments.
+    pub fn snapshot(&self) -> ProgressSnapshot {
+        ProgressSnapshot {
+            prefixes_discovered: self.prefixes_discovered.load(Ordering::Relaxed),
+            prefixes_listed: self.prefixes_listed.load(Ordering::Relaxed),
+            objects_found: self.objects_found.load(Ordering::Relaxed),
+            bytes_seen: self.bytes_seen.load(Ordering::Relaxed),
+            max_depth: self.max_depth.load(Ordering::Relaxed),
+            requests_in_flight: self.requests_in_flight.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn on_discovered(&self) {
+        self.prefixes_discovered.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn on_listed(&self, depth: usize, list_result: &ListResult) {
+        let bytes: u64 = list_result
+            .objects
+            .iter()
+            .map(|object_meta| object_meta.size as u64)
+            .sum();
+        self.prefixes_listed.fetch_add(1, Ordering::Relaxed);
+        self.objects_found
+            .fetch_add(list_result.objects.len(), Ordering::Relaxed);
+        self.bytes_seen.fetch_add(bytes, Ordering::Relaxed);
+        self.max_depth.fetch_max(depth, Ordering::Relaxed);
+    }
+
+    /// Counts a request as in flight until the returned guard is dropped (which also
+    /// happens if the request is cancelled).
+    pub(crate) fn start_request(&self) -> RequestGuard<'_> {
+        self.requests_in_flight.fetch_add(1, Ordering::Relaxed);
+        RequestGuard { progress: self }
+    }
+}
+
+/// Returned by [`ListProgress::start_request`].
+pub(crate) struct RequestGuard<'a> {
+    progress: &'a ListProgress,
+}
+
+impl Drop for RequestGuard<'_> {
+    fn drop(&mut self) {
+        self.progress
+            .requests_in_flight
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,174 @@
+use std::{sync::Mutex, time::Duration};
+
+use tokio::time::Instant;
+
+/// A token-bucket rate limiter for `list_with_delimiter` requests. Set
+/// [`ListOptions::rate_limiter`](crate::ListOptions::rate_limiter) to use it.
+///
+/// The bucket holds up to `burst` tokens, and refills at `requests_per_second`. Each
+/// request takes one token, waiting for the bucket to refill if it's empty. So, on
+/// average, no more than `requests_per_second` requests are made per second, but up to
+/// `burst` requests can be made at once after a quiet period.
+///
+/// To apply one limit across several concurrent listings, share the same
+/// `Arc<RateLimiter>` between their [`ListOptions`](crate::ListOptions):
+///
+/// ```
+/// use std::sync::Arc;
+/// use list_with_depth::{ListOptions, RateLimiter};
+///
+/// let rate_limiter = Arc::new(RateLimiter::new(100.0, 10));
+/// let options = ListOptions {
+///     rate_limiter: Some(rate_limiter.clone()),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug)]
+pub struct RateLimiter {
+    requests_per_second: f64,
+    burst: f64,
+    state: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// The number of tokens in the bucket. Negative if requests are waiting for tokens.
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    /// Creates a rate limiter whose bucket starts full.
+    ///
+    /// # Panics
+    /// If `requests_per_second` is not a positive, finite number.
+    pub fn new(requests_per_second: f64, burst: usize) -> Self {
+        assert!(
+            requests_per_second.is_finite() && requests_per_second > 0.0,
+            "requests_per_second must be positive, not {requests_per_second}"
+        );
+        let burst = burst.max(1) as f64;
+        Self {
+            requests_per_second,
+            burst,
+            state: Mutex::new(Bucket {
+                tokens: burst,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Waits until a request is allowed.
+    pub(crate) async fn acquire(&self) {
+        let wait = {
+            let mut bucket = self.state.lock().unwrap();
+            let now = Instant::now();
+            let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * self.requests_per_second).min(self.burst);
+            bucket.last_refill = now;
+            // Reserve a token now, even if that takes the bucket below zero. Each waiter
+            // then sleeps until its own token has been refilled, so requests are let
+            // through in the order they arrived.
+            bucket.tokens -= 1.0;
+            if bucket.tokens < 0.0 {
+                Duration::from_secs_f64(-bucket.tokens / self.requests_per_second)
+            } else {
+                Duration::ZERO
+            }
+        };
+        if !wait.is_zero() {
+            // If the request is cancelled while it waits (by a deadline or a request
+            // timeout, or because the stream was dropped), give its token back. Otherwise
+            // it'd hold up every other listing which shares this rate limiter.
+            let reservation = Reservation(self);
+            tokio::time::sleep(wait).await;
+            reservation.keep();
+        }
+    }
+}
+
+/// A token taken by [`RateLimiter::acquire`], which is returned to the bucket on drop
+/// unless it's kept.
+struct Reservation<'a>(&'a RateLimiter);
+
+impl Reservation<'_> {
+    fn keep(self) {
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        let mut bucket = self.0.state.lock().unwrap();
+        bucket.tokens = (bucket.tokens + 1.0).min(self.0.burst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_burst_then_steady_rate() {
+        let rate_limiter = RateLimiter::new(10.0, 5);
+        let start = Instant::now();
+        for _ in 0..5 {
+            rate_limiter.acquire().await;
+        }
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        for _ in 0..10 {
+            rate_limiter.acquire().await;
+        }
+        assert_eq!(start.elapsed().as_millis(), 1000);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_bucket_refills_up_to_burst() {
+        let rate_limiter = RateLimiter::new(10.0, 2);
+        rate_limiter.acquire().await;
+        rate_limiter.acquire().await;
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        let start = Instant::now();
+        for _ in 0..3 {
+            rate_limiter.acquire().await;
+        }
+        assert_eq!(start.elapsed().as_millis(), 100);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_concurrent_waiters() {
+        let rate_limiter = std::sync::Arc::new(RateLimiter::new(100.0, 1));
+        let start = Instant::now();
+        let handles: Vec<_> = (0..50)
+            .map(|_| {
+                let rate_limiter = rate_limiter.clone();
+                tokio::spawn(async move { rate_limiter.acquire().await })
+            })
+            .collect();
+        for handle in handles {
+            handle.await.unwrap();
+        }
+        assert_eq!(start.elapsed().as_millis(), 490);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cancelled_waiters_return_their_tokens() {
+        let rate_limiter = RateLimiter::new(10.0, 1);
+        rate_limiter.acquire().await;
+        for _ in 0..50 {
+            let acquire = rate_limiter.acquire();
+            assert!(tokio::time::timeout(Duration::from_millis(1), acquire)
+                .await
+                .is_err());
+        }
+        // 50ms have passed, so the bucket is half refilled: the cancelled waiters didn't
+        // keep their tokens.
+        let start = Instant::now();
+        rate_limiter.acquire().await;
+        assert!(
+            start.elapsed() <= Duration::from_millis(50),
+            "{:?}",
+            start.elapsed()
+        );
+    }
+}
@@ -0,0 +1,282 @@
+use std::io::{self, Write};
+
+use object_store::{path::Path, ObjectMeta};
+
+use crate::ListTree;
+
+/// How [`write_tree`] draws a [`ListTree`].
+#[derive(Debug, Clone)]
+pub struct TreeFormat {
+    /// The characters used to draw the branches.
+    pub charset: Charset,
+    /// Show the size of each object, in bytes.
+    pub sizes: bool,
+    /// Show the `last_modified` time of each object.
+    pub modified: bool,
+    /// Show the number of objects and prefixes directly beneath each listed prefix.
+    pub counts: bool,
+    /// The maximum number of entries to show beneath each prefix. The rest are replaced
+    /// by a single line saying how many were left out. `None` means show everything.
+    pub max_entries: Option<usize>,
+}
+
+impl Default for TreeFormat {
+    fn default() -> Self {
+        Self {
+            charset: Charset::Unicode,
+            sizes: false,
+            modified: false,
+            counts: true,
+            max_entries: None,
+        }
+    }
+}
+
+/// The characters used to draw the branches of a tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Charset {
+    /// `├──`, `└──` and `│`.
+    Unicode,
+    /// `|--`, `` `-- `` and `|`, for terminals which can't display Unicode.
+    Ascii,
+}
+
+impl Charset {
+    /// Returns the (branch, last branch, vertical line) strings.
+    fn branches(self) -> (&'static str, &'static str, &'static str) {
+        match self {
+            Self::Unicode => ("├── ", "└── ", "│   "),
+            Self::Ascii => ("|-- ", "`-- ", "|   "),
+        }
+    }
+}
+
+/// A line beneath a prefix.
+enum Entry<'a> {
+    /// A prefix which was listed.
+    Node(&'a ListTree),
+    /// A prefix below the requested depth, which wasn't listed.
+    LeafPrefix(&'a Path),
+    Object(&'a ObjectMeta),
+}
+
+impl Entry<'_> {
+    fn path(&self) -> &Path {
+        match self {
+            Self::Node(node) => &node.prefix,
+            Self::LeafPrefix(prefix) => prefix,
+            Self::Object(object_meta) => &object_meta.location,
+        }
+    }
+}
+
+/// Draws `tree` like the Unix `tree` command, e.g.:
+///
+/// ```text
+/// foo/ (1 object, 2 prefixes)
+/// ├── b.txt
+/// ├── bar/ (2 objects, 0 prefixes)
+/// │   ├── c.txt
+/// │   └── d.txt
+/// └── baz/ (1 object, 1 prefix)
+///     ├── bleh/
+///     └── e.txt
+///
+/// 3 prefixes, 4 objects
+/// ```
+///
+/// Prefixes end with `/`. Prefixes below the depth of the tree weren't listed, so they
+/// have no counts. The last line is the total number of prefixes and objects shown.
+pub fn write_tree(out: &mut impl Write, tree: &ListTree, format: &TreeFormat) -> io::Result<()> {
+    let root_name = if tree.prefix.as_ref().is_empty() {
+        ".".to_string()
+    } else {
+        format!("{}/", tree.prefix)
+    };
+    write!(out, "{root_name}")?;
+    write_counts(out, tree, format)?;
+    writeln!(out)?;
+    let mut totals = (0, 0);
+    write_children(out, tree, format, "", &mut totals)?;
+    let (n_prefixes, n_objects) = totals;
+    writeln!(
+        out,
+        "\n{} {}, {} {}",
+        n_prefixes,
+        plural(n_prefixes, "prefix", "prefixes"),
+        n_objects,
+        plural(n_objects, "object", "objects")
+    )
+}
+
+fn write_children(
+    out: &mut impl Write,
+    tree: &ListTree,
+    format: &TreeFormat,
+    indent: &str,
+    totals: &mut (usize, usize),
+) -> io::Result<()> {
+    let mut entries: Vec<Entry<'_>> = tree
+        .children
+        .iter()
+        .map(Entry::Node)
+        .chain(tree.leaf_prefixes.iter().map(Entry::LeafPrefix))
+        .chain(tree.objects.iter().map(Entry::Object))
+        .collect();
+    entries.sort_by(|a, b| a.path().cmp(b.path()));
+    let n_entries = entries.len();
+    let n_shown = format.max_entries.unwrap_or(usize::MAX).min(n_entries);
+    let (branch, last_branch, vertical) = format.charset.branches();
+
+    for (i, entry) in entries.iter().take(n_shown).enumerate() {
+        let is_last = i + 1 == n_entries;
+        write!(
+            out,
+            "{indent}{}",
+            if is_last { last_branch } else { branch }
+        )?;
+        let name = entry.path().filename().unwrap_or_default();
+        match entry {
+            Entry::Node(node) => {
+                totals.0 += 1;
+                write!(out, "{name}/")?;
+                write_counts(out, node, format)?;
+                writeln!(out)?;
+                let indent = format!("{indent}{}", if is_last { "    " } else { vertical });
+                write_children(out, node, format, &indent, totals)?;
+            }
+            Entry::LeafPrefix(_) => {
+                totals.0 += 1;
+                writeln!(out, "{name}/")?;
+            }
+            Entry::Object(object_meta) => {
+                totals.1 += 1;
+                write_object_details(out, object_meta, format)?;
+                writeln!(out, "{name}")?;
+            }
+        }
+    }
+    if n_shown < n_entries {
+        writeln!(out, "{indent}{last_branch}... {} more", n_entries - n_shown)?;
+    }
+    Ok(())
+}
+
+fn write_counts(out: &mut impl Write, tree: &ListTree, format: &TreeFormat) -> io::Result<()> {
+    if !format.counts {
+        return Ok(());
+    }
+    let n_objects = tree.objects.len();
+    let n_prefixes = tree.children.len() + tree.leaf_prefixes.len();
+    write!(
+        out,
+        " ({} {}, {} {})",
+        n_objects,
+        plural(n_objects, "object", "objects"),
+        n_prefixes,
+        plural(n_prefixes, "prefix", "prefixes")
+    )
+}
+
+fn write_object_details(
+    out: &mut impl Write,
+    object_meta: &ObjectMeta,
+    format: &TreeFormat,
+) -> io::Result<()> {
+    match (format.sizes, format.modified) {
+        (false, false) => Ok(()),
+        (true, false) => write!(out, "[{:>12}]  ", object_meta.size),
+        (false, true) => write!(
+            out,
+            "[{}]  ",
+            object_meta.last_modified.format("%Y-%m-%d %H:%M")
+        ),
+        (true, true) => write!(
+            out,
+            "[{:>12} {}]  ",
+            object_meta.size,
+            object_meta.last_modified.format("%Y-%m-%d %H:%M")
+        ),
+    }
+}
+
+fn plural(n: usize, singular: &'static str, plural: &'static str) -> &'static str {
+    if n == 1 {
+        singular
+    } else {
+        plural
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{list_tree, test_utils::create_in_memory_store};
+
+    use super::*;
+
+    async fn render(prefix: Option<&str>, depth: usize, format: &TreeFormat) -> String {
+        let store = Arc::new(create_in_memory_store().await.unwrap());
+        let prefix = prefix.map(Path::from);
+        let tree = list_tree(store, prefix.as_ref(), depth).await.unwrap();
+        let mut out = vec![];
+        write_tree(&mut out, &tree, format).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_write_tree() {
+        let rendered = render(Some("foo"), 1, &TreeFormat::default()).await;
+        let expected = "\
+foo/ (1 object, 2 prefixes)
+├── b.txt
+├── bar/ (2 objects, 0 prefixes)
+│   ├── c.txt
+│   └── d.txt
+└── baz/ (1 object, 1 prefix)
+    ├── bleh/
+    └── e.txt
+
+3 prefixes, 4 objects
+";
+        assert_eq!(rendered, expected);
+    }
+
+    #[tokio::test]
+    async fn test_write_tree_ascii_truncated() {
+        let format = TreeFormat {
+            charset: Charset::Ascii,
+            counts: false,
+            max_entries: Some(1),
+            ..Default::default()
+        };
+        let rendered = render(None, 2, &format).await;
+        let expected = "\
+.
+|-- a.txt
+`-- ... 1 more
+
+0 prefixes, 1 object
+";
+        assert_eq!(rendered, expected);
+    }
+
+    #[tokio::test]
+    async fn test_write_tree_sizes() {
+        let format = TreeFormat {
+            sizes: true,
+            counts: false,
+            ..Default::default()
+        };
+        let rendered = render(Some("foo/bar"), 0, &format).await;
+        let expected = "\
+foo/bar/
+├── [           0]  c.txt
+└── [           0]  d.txt
+
+0 prefixes, 2 objects
+";
+        assert_eq!(rendered, expected);
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+/// Configures how listing a single prefix is retried after a failure. Set
+/// [`ListOptions::retry`](crate::ListOptions::retry) to enable retries.
+///
+/// Only the failed prefix is retried: the rest of the traversal carries on in the meantime.
+/// Note that `object_store`'s HTTP client has its own retry logic, which applies to each
+/// HTTP request. This is an extra layer on top, which applies to each prefix.
+///
+/// The delay before retry `n` (where the first retry is `n = 1`) is
+/// `min(initial_backoff * base^(n-1), max_backoff)`. If `jitter` is `true` then the delay
+/// is then chosen uniformly at random between zero and that value ("full jitter"), so that
+/// many failed prefixes don't all retry at the same moment. The delay is always clamped
+/// to between zero and `max_backoff`, even if `base` is negative or not finite.
+#[derive(Debug, Clone)]
+pub struct RetryConfig {
+    /// The maximum number of attempts to list each prefix, including the first attempt.
+    pub max_attempts: usize,
+    /// The delay before the first retry.
+    pub initial_backoff: Duration,
+    /// The maximum delay between attempts.
+    pub max_backoff: Duration,
+    /// The multiplier applied to the delay after each retry.
+    pub base: f64,
+    /// Whether to randomize the delay.
+    pub jitter: bool,
+    /// Returns `true` if an error is worth retrying. Defaults to [`is_retryable`].
+    pub is_retryable: fn(&object_store::Error) -> bool,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            base: 2.0,
+            jitter: true,
+            is_retryable,
+        }
+    }
+}
+
+impl RetryConfig {
+    /// The delay before retry number `retry` (where the first retry is `1`).
+    pub(crate) fn backoff(&self, retry: usize) -> Duration {
+        let exponent = retry.saturating_sub(1).min(i32::MAX as usize) as i32;
+        let backoff = self.initial_backoff.as_secs_f64() * self.base.powi(exponent);
+        // `f64::min` and `f64::max` ignore NaN, so this also clamps NaN to `max_backoff`.
+        let backoff = backoff.min(self.max_backoff.as_secs_f64()).max(0.0);
+        let backoff = if self.jitter {
+            backoff * random_fraction()
+        } else {
+            backoff
+        };
+        // The conversion can only fail if `max_backoff` is too big to round-trip via f64.
+        Duration::try_from_secs_f64(backoff).unwrap_or(self.max_backoff)
+    }
+}
+
+/// The default classifier for [`RetryConfig::is_retryable`].
+///
+/// Returns `false` for errors which will almost certainly happen again, like
+/// [`object_store::Error::NotFound`] or [`object_store::Error::PermissionDenied`], and
+/// `true` for everything else (including [`object_store::Error::Generic`], which is how
+/// `object_store` reports server errors and timeouts).
+pub fn is_retryable(e: &object_store::Error) -> bool {
+    use object_store::Error::*;
+    !matches!(
+        e,
+        NotFound { .. }
+            | InvalidPath { .. }
+            | NotSupported { .. }
+            | AlreadyExists { .. }
+            | Precondition { .. }
+            | NotModified { .. }
+            | NotImplemented
+            | PermissionDenied { .. }
+            | Unauthenticated { .. }
+            | UnknownConfigurationKey { .. }
+    )
+}
+
+/// Returns a pseudo-random number in `[0, 1)`.
+///
+/// This doesn't need to be high quality, so we use the randomly-seeded hasher from the
+/// standard library, rather than pulling in a dependency.
+fn random_fraction() -> f64 {
+    let random_bits = RandomState::new().build_hasher().finish();
+    // Use the top 53 bits, which is the precision of an f64's mantissa.
+    (random_bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_without_jitter() {
+        let retry_config = RetryConfig {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+            jitter: false,
+            ..Default::default()
+        };
+        let backoffs: Vec<u128> = (1..=5)
+            .map(|retry| retry_config.backoff(retry).as_millis())
+            .collect();
+        assert_eq!(backoffs, vec![100, 200, 400, 500, 500]);
+    }
+
+    #[test]
+    fn test_backoff_is_clamped() {
+        for base in [-2.0, f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            let retry_config = RetryConfig {
+                base,
+                jitter: false,
+                ..Default::default()
+            };
+            for retry in 1..=5 {
+                let backoff = retry_config.backoff(retry);
+                assert!(backoff <= retry_config.max_backoff, "{base} {retry}");
+            }
+        }
+        let retry_config = RetryConfig {
+            base: -2.0,
+            jitter: false,
+            ..Default::default()
+        };
+        assert_eq!(retry_config.backoff(2), Duration::ZERO);
+        let retry_config = RetryConfig {
+            max_backoff: Duration::MAX,
+            jitter: false,
+            ..Default::default()
+        };
+        assert_eq!(retry_config.backoff(2000), Duration::MAX);
+    }
+
+    #[test]
+    fn test_backoff_with_jitter() {
+        let retry_config = RetryConfig::default();
+        for retry in 1..=10 {
+            let backoff = retry_config.backoff(retry);
+            assert!(backoff <= retry_config.max_backoff);
+        }
+        let backoffs: Vec<Duration> = (0..10).map(|_| retry_config.backoff(4)).collect();
+        assert!(backoffs.iter().any(|backoff| *backoff != backoffs[0]));
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        let generic = object_store::Error::Generic {
+            store: "test",
+            source: "503 Service Unavailable".into(),
+        };
+        assert!(is_retryable(&generic));
+        let not_found = object_store::Error::NotFound {
+            path: "foo".to_string(),
+            source: "not found".into(),
+        };
+        assert!(!is_retryable(&not_found));
+    }
+}
@@ -0,0 +1,88 @@
+use std::{sync::Mutex, time::Duration};
+
+use object_store::path::Path;
+use tokio::time::Instant;
+
+/// Statistics about a completed (or running) traversal, from
+/// [`list_with_depth_stats`](crate::list_with_depth_stats) or
+/// [`ListStream::stats`](crate::ListStream::stats).
+///
+/// Object stores charge per `list_with_delimiter` request, so `requests` is a direct
+/// measure of the cost of a listing. `prefixes_per_depth` shows how quickly the tree fans
+/// out, which helps to choose the depth to list at.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ListStats {
+    /// The number of `list_with_delimiter` requests, including failed requests and retries.
+    pub requests: usize,
+    /// The number of requests which were retries (see
+    /// [`ListOptions::retry`](crate::ListOptions::retry)).
+    pub retries: usize,
+    /// The number of prefixes listed successfully at each depth. `prefixes_per_depth[0]`
+    /// is `1` (the prefix that the traversal started from), unless that listing failed.
+    pub prefixes_per_depth: Vec<usize>,
+    /// The prefix whose `list_with_delimiter` request took longest, and how long it took.
+    /// `None` if no requests finished.
+    pub slowest_prefix: Option<(Path, Duration)>,
+    /// The time from the start of the traversal until it finished (or until now, if it's
+    /// still running).
+    pub elapsed: Duration,
+}
+
+/// Records [`ListStats`] for a single traversal.
+#[derive(Debug)]
+pub(crate) struct StatsRecorder {
+    start: Instant,
+    state: Mutex<StatsState>,
+}
+
+#[derive(Debug, Default)]
+struct StatsState {
+    stats: ListStats,
+    end: Option<Instant>,
+}
+
+impl StatsRecorder {
+    pub(crate) fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            state: Mutex::new(StatsState::default()),
+        }
+    }
+
+    pub(crate) fn on_request(&self, prefix: Option<&Path>, latency: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.stats.requests += 1;
+        let is_slowest = match &state.stats.slowest_prefix {
+            Some((_, slowest_latency)) => latency > *slowest_latency,
+            None => true,
+        };
+        if is_slowest {
+            state.stats.slowest_prefix = Some((prefix.cloned().unwrap_or_default(), latency));
+        }
+    }
+
+    pub(crate) fn on_retry(&self) {
+        self.state.lock().unwrap().stats.retries += 1;
+    }
+
+    pub(crate) fn on_listed(&self, depth: usize) {
+        let mut state = self.state.lock().unwrap();
+        let prefixes_per_depth = &mut state.stats.prefixes_per_depth;
+        if prefixes_per_depth.len() <= depth {
+            prefixes_per_depth.resize(depth + 1, 0);
+        }
+        prefixes_per_depth[depth] += 1;
+    }
+
+    pub(crate) fn on_finished(&self) {
+        self.state.lock().unwrap().end = Some(Instant::now());
+    }
+
+    pub(crate) fn stats(&self) -> ListStats {
+        let state = self.state.lock().unwrap();
+        ListStats {
+            elapsed: state.end.unwrap_or_else(Instant::now) - self.start,
+            ..state.stats.clone()
+        }
+    }
+}
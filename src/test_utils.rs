@@ -0,0 +1,182 @@
+//! Helpers shared by the unit tests in this crate.
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use object_store::{
+    memory::InMemory, path::Path, GetOptions, GetResult, ListResult, MultipartUpload, ObjectMeta,
+    ObjectStore, PutMultipartOpts, PutOptions, PutPayload, PutResult,
+};
+use tokio::time::Instant;
+
+pub(crate) async fn create_in_memory_store() -> object_store::Result<InMemory> {
+    const KEYS: [&str; 6] = [
+        "a.txt",
+        "foo/b.txt",
+        "foo/bar/c.txt",
+        "foo/bar/d.txt",
+        "foo/baz/e.txt",
+        "foo/baz/bleh/f.txt",
+    ];
+    let store = InMemory::new();
+    for key in KEYS {
+        store.put(&key.into(), PutPayload::new()).await?;
+    }
+    Ok(store)
+}
+
+/// Wraps an [`InMemory`] store, adds latency to `list_with_delimiter` and records
+/// how many `list_with_delimiter` requests were in flight.
+#[derive(Debug)]
+pub(crate) struct MockStore {
+    inner: InMemory,
+    delay: Duration,
+    prefix_delays: HashMap<Path, Duration>,
+    /// The number of times that listing each prefix will fail before succeeding.
+    prefix_errors: Mutex<HashMap<Path, usize>>,
+    /// The prefixes which panic when they're listed.
+    prefix_panics: HashSet<Path>,
+    in_flight: AtomicUsize,
+    pub(crate) max_in_flight: AtomicUsize,
+    pub(crate) list_requests: AtomicUsize,
+    /// When each `list_with_delimiter` request started, in order.
+    pub(crate) request_starts: Mutex<Vec<Instant>>,
+}
+
+impl MockStore {
+    pub(crate) fn new(inner: InMemory, delay: Duration) -> Self {
+        Self {
+            inner,
+            delay,
+            prefix_delays: HashMap::new(),
+            prefix_errors: Mutex::new(HashMap::new()),
+            prefix_panics: HashSet::new(),
+            in_flight: AtomicUsize::new(0),
+            max_in_flight: AtomicUsize::new(0),
+            list_requests: AtomicUsize::new(0),
+            request_starts: Mutex::new(vec![]),
+        }
+    }
+
+    /// Creates a store with `n` prefixes directly beneath the root, each of which
+    /// contains one object.
+    pub(crate) async fn with_n_prefixes(n: usize, delay: Duration) -> object_store::Result<Self> {
+        let inner = InMemory::new();
+        for i in 0..n {
+            let key = Path::from(format!("{i:04}/data.bin"));
+            inner.put(&key, PutPayload::new()).await?;
+        }
+        Ok(Self::new(inner, delay))
+    }
+
+    /// Overrides the latency for listing `prefix`.
+    pub(crate) fn with_prefix_delay(mut self, prefix: Path, delay: Duration) -> Self {
+        self.prefix_delays.insert(prefix, delay);
+        self
+    }
+
+    /// Makes the first `times` attempts to list `prefix` fail with a generic error.
+    pub(crate) fn with_prefix_error(self, prefix: Path, times: usize) -> Self {
+        self.prefix_errors.lock().unwrap().insert(prefix, times);
+        self
+    }
+
+    /// Makes listing `prefix` panic.
+    pub(crate) fn with_prefix_panic(mut self, prefix: Path) -> Self {
+        self.prefix_panics.insert(prefix);
+        self
+    }
+
+    /// Returns `true` if this attempt to list `prefix` should fail.
+    fn should_fail(&self, prefix: &Path) -> bool {
+        let mut prefix_errors = self.prefix_errors.lock().unwrap();
+        match prefix_errors.get_mut(prefix) {
+            Some(0) | None => false,
+            Some(times) => {
+                *times -= 1;
+                true
+            }
+        }
+    }
+}
+
+impl fmt::Display for MockStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MockStore({})", self.inner)
+    }
+}
+
+#[async_trait]
+impl ObjectStore for MockStore {
+    async fn put_opts(
+        &self,
+        location: &Path,
+        payload: PutPayload,
+        opts: PutOptions,
+    ) -> object_store::Result<PutResult> {
+        self.inner.put_opts(location, payload, opts).await
+    }
+
+    async fn put_multipart_opts(
+        &self,
+        location: &Path,
+        opts: PutMultipartOpts,
+    ) -> object_store::Result<Box<dyn MultipartUpload>> {
+        self.inner.put_multipart_opts(location, opts).await
+    }
+
+    async fn get_opts(
+        &self,
+        location: &Path,
+        options: GetOptions,
+    ) -> object_store::Result<GetResult> {
+        self.inner.get_opts(location, options).await
+    }
+
+    async fn delete(&self, location: &Path) -> object_store::Result<()> {
+        self.inner.delete(location).await
+    }
+
+    fn list(&self, prefix: Option<&Path>) -> BoxStream<'_, object_store::Result<ObjectMeta>> {
+        self.inner.list(prefix)
+    }
+
+    async fn list_with_delimiter(&self, prefix: Option<&Path>) -> object_store::Result<ListResult> {
+        self.list_requests.fetch_add(1, Ordering::SeqCst);
+        self.request_starts.lock().unwrap().push(Instant::now());
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+        let delay = prefix
+            .and_then(|prefix| self.prefix_delays.get(prefix))
+            .unwrap_or(&self.delay);
+        tokio::time::sleep(*delay).await;
+        let path = prefix.cloned().unwrap_or_default();
+        assert!(!self.prefix_panics.contains(&path), "injected panic");
+        let result = if self.should_fail(&path) {
+            Err(object_store::Error::Generic {
+                store: "MockStore",
+                source: "injected failure".into(),
+            })
+        } else {
+            self.inner.list_with_delimiter(prefix).await
+        };
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        result
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+        self.inner.copy(from, to).await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+        self.inner.copy_if_not_exists(from, to).await
+    }
+}
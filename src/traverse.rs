@@ -0,0 +1,428 @@
+//! The recursive traversal which underpins all the public listing functions.
+use std::{
+    future::Future,
+    ops::RangeInclusive,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures::Stream;
+use object_store::{path::Path, ListResult, ObjectStore};
+use tokio::{
+    sync::{mpsc, Semaphore},
+    task::{JoinHandle, JoinSet},
+    time::Instant,
+};
+
+use crate::{
+    adaptive::AdaptiveLimiter, stats::StatsRecorder, Error, ListOptions, ListStats, ListVisitor,
+    Result,
+};
+
+/// The number of [`PrefixListing`]s that can be buffered in a [`ListStream`]
+/// before the traversal waits for the consumer to catch up.
+const STREAM_BUFFER_SIZE: usize = 64;
+
+/// The [`ListResult`] for a single prefix within the requested depth(s).
+#[derive(Debug)]
+pub struct PrefixListing {
+    /// The prefix that was listed. This is the empty path for the root of the store.
+    pub prefix: Path,
+    /// The depth of `prefix`, relative to the prefix that the traversal started from.
+    pub depth: usize,
+    /// The objects and common prefixes directly beneath `prefix`.
+    pub list_result: ListResult,
+}
+
+/// A [`Stream`] of [`PrefixListing`]s, returned by [`list_with_depth_stream`](crate::list_with_depth_stream).
+///
+/// Dropping the `ListStream` aborts any requests which are still in flight.
+#[derive(Debug)]
+pub struct ListStream {
+    receiver: mpsc::Receiver<Result<PrefixListing>>,
+    driver: JoinHandle<()>,
+    stats: Arc<StatsRecorder>,
+}
+
+impl ListStream {
+    /// Statistics about the traversal so far. Once the stream has ended, these are the
+    /// final statistics.
+    pub fn stats(&self) -> ListStats {
+        self.stats.stats()
+    }
+}
+
+impl Stream for ListStream {
+    type Item = Result<PrefixListing>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+impl Drop for ListStream {
+    fn drop(&mut self) {
+        // Aborting the driver drops its `JoinSet`, which aborts all the spawned tasks.
+        self.driver.abort();
+    }
+}
+
+/// The state shared by every level of a single traversal.
+struct Traversal {
+    store: Arc<dyn ObjectStore>,
+    options: ListOptions,
+    /// Limits the number of `list_with_delimiter` requests in flight across all levels.
+    concurrency_limit: Option<Semaphore>,
+    /// Adjusts the number of requests in flight in response to throttling.
+    adaptive_limiter: Option<AdaptiveLimiter>,
+    /// The depths at which listings are sent to `tx`. We don't descend deeper than the end.
+    depths: RangeInclusive<usize>,
+    visitor: Option<Arc<dyn ListVisitor>>,
+    /// If `true` then failures are sent to `tx` and the rest of the traversal carries on.
+    /// If `false` then the first failure ends the traversal.
+    keep_going: bool,
+    tx: mpsc::Sender<Result<PrefixListing>>,
+    stats: Arc<StatsRecorder>,
+}
+
+impl Traversal {
+    /// Lists `prefix`, giving up if the deadline passes. `parents` are the ancestors of
+    /// `prefix`, and are only used for error reporting.
+    async fn list(
+        &self,
+        prefix: Option<&Path>,
+        depth: usize,
+        parents: &[Path],
+    ) -> Result<ListResult> {
+        let result = match self.options.deadline {
+            Some(deadline) => {
+                match tokio::time::timeout_at(deadline, self.list_with_retries(prefix)).await {
+                    Ok(result) => result,
+                    Err(_) => {
+                        return Err(Error::DeadlineExceeded {
+                            path: prefix.cloned().unwrap_or_default(),
+                            depth,
+                            parents: parents.to_vec(),
+                        })
+                    }
+                }
+            }
+            None => self.list_with_retries(prefix).await,
+        };
+        result.map_err(|source| Error::List {
+            path: prefix.cloned().unwrap_or_default(),
+            depth,
+            parents: parents.to_vec(),
+            source,
+        })
+    }
+
+    /// Returns `true` if the deadline has passed, so no more prefixes should be listed.
+    fn deadline_passed(&self) -> bool {
+        self.options
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Lists `prefix`, retrying (if configured) after a failure.
+    async fn list_with_retries(&self, prefix: Option<&Path>) -> object_store::Result<ListResult> {
+        let mut attempt = 1;
+        loop {
+            let e = match self.list_once(prefix).await {
+                Ok(list_result) => return Ok(list_result),
+                Err(e) => e,
+            };
+            match &self.options.retry {
+                Some(retry) if attempt < retry.max_attempts && (retry.is_retryable)(&e) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(attempt, error = %e, "retrying list_with_delimiter");
+                    tokio::time::sleep(retry.backoff(attempt)).await;
+                    self.stats.on_retry();
+                    attempt += 1;
+                }
+                _ => return Err(e),
+            }
+        }
+    }
+
+    /// Calls `list_with_delimiter`, waiting for concurrency permits first (if necessary).
+    async fn list_once(&self, prefix: Option<&Path>) -> object_store::Result<ListResult> {
+        // The permits are only held for the duration of the request (not while we wait for
+        // the children), so deep trees can't deadlock the traversal.
+        let _permit = match &self.concurrency_limit {
+            Some(semaphore) => Some(
+                semaphore
+                    .acquire()
+                    .await
+                    .expect("the semaphore is never closed"),
+            ),
+            None => None,
+        };
+        let mut adaptive_permit = match &self.adaptive_limiter {
+            Some(adaptive_limiter) => Some(adaptive_limiter.acquire().await),
+            None => None,
+        };
+        // Wait for the rate limiter last, immediately before the request. If we took a
+        // token before waiting for the permits, then a slow request would leave tokens
+        // waiting for permits, which would all be spent at once when it finished.
+        if let Some(rate_limiter) = &self.options.rate_limiter {
+            rate_limiter.acquire().await;
+            // So the adaptive limiter only measures the latency of the request.
+            if let Some(adaptive_permit) = &mut adaptive_permit {
+                adaptive_permit.restart_clock();
+            }
+        }
+        let _request_guard = self
+            .options
+            .progress
+            .as_ref()
+            .map(|progress| progress.start_request());
+        let start = Instant::now();
+        let request = self.store.list_with_delimiter(prefix);
+        let result = match self.options.request_timeout {
+            Some(request_timeout) => tokio::time::timeout(request_timeout, request)
+                .await
+                .unwrap_or_else(|_| {
+                    Err(object_store::Error::Generic {
+                        store: "list_with_depth",
+                        source: format!("list_with_delimiter timed out after {request_timeout:?}")
+                            .into(),
+                    })
+                }),
+            None => request.await,
+        };
+        self.stats.on_request(prefix, start.elapsed());
+        if let Some(adaptive_permit) = adaptive_permit {
+            adaptive_permit.finish(&result);
+        }
+        result
+    }
+
+    /// Reports a failure. Returns `Err` if the traversal should stop.
+    async fn report(&self, e: Error) -> Result<()> {
+        if self.keep_going {
+            let _ = self.tx.send(Err(e)).await;
+            Ok(())
+        } else {
+            Err(e)
+        }
+    }
+}
+
+/// Spawns a task which lists `prefix` down to the end of `depths`, and returns a
+/// [`ListStream`] which yields the [`PrefixListing`]s for every prefix within `depths`.
+/// If a `visitor` is given then it's consulted at every level. If `keep_going` is `true`
+/// then the stream yields every failure, instead of ending after the first failure.
+pub(crate) fn spawn_traversal(
+    store: Arc<dyn ObjectStore>,
+    prefix: Option<&Path>,
+    depths: RangeInclusive<usize>,
+    visitor: Option<Arc<dyn ListVisitor>>,
+    keep_going: bool,
+    options: ListOptions,
+) -> ListStream {
+    let frontier = vec![(prefix.cloned().unwrap_or_default(), 0, vec![])];
+    spawn_traversal_from(store, frontier, depths, visitor, keep_going, options)
+}
+
+/// Like [`spawn_traversal`], but starts from several prefixes at once. Each item in
+/// `frontier` is a prefix, its depth and its parents (as for [`next_level`]).
+pub(crate) fn spawn_traversal_from(
+    store: Arc<dyn ObjectStore>,
+    frontier: Vec<(Path, usize, Vec<Path>)>,
+    depths: RangeInclusive<usize>,
+    visitor: Option<Arc<dyn ListVisitor>>,
+    keep_going: bool,
+    options: ListOptions,
+) -> ListStream {
+    let (tx, receiver) = mpsc::channel(STREAM_BUFFER_SIZE);
+    let stats = Arc::new(StatsRecorder::new());
+    let traversal = Arc::new(Traversal {
+        store,
+        concurrency_limit: options
+            .max_concurrency
+            .map(|max_concurrency| Semaphore::new(max_concurrency.max(1))),
+        adaptive_limiter: options
+            .adaptive_concurrency
+            .clone()
+            .map(AdaptiveLimiter::new),
+        options,
+        depths,
+        visitor,
+        keep_going,
+        tx,
+        stats: stats.clone(),
+    });
+    // Create the span here (rather than inside the task) so that its parent is the
+    // caller's span.
+    #[cfg(feature = "tracing")]
+    let span = tracing::info_span!(
+        "list_with_depth",
+        depths = ?traversal.depths,
+        frontier = frontier.len(),
+    );
+    let task = async move {
+        let result = visit_all(&traversal, frontier).await;
+        // Record the end before the stream ends (when the last `tx` is dropped).
+        traversal.stats.on_finished();
+        if let Err(e) = result {
+            let _ = traversal.tx.send(Err(e)).await;
+        }
+    };
+    #[cfg(feature = "tracing")]
+    let task = tracing::Instrument::instrument(task, span);
+    let driver = tokio::spawn(task);
+    ListStream {
+        receiver,
+        driver,
+        stats,
+    }
+}
+
+/// Concurrently lists and descends into every prefix in `frontier`, whose items are as
+/// for [`spawn_traversal_from`].
+async fn visit_all(
+    traversal: &Arc<Traversal>,
+    frontier: impl IntoIterator<Item = (Path, usize, Vec<Path>)>,
+) -> Result<()> {
+    let mut set = JoinSet::new();
+    for (prefix, depth, parents) in frontier {
+        if let Some(progress) = &traversal.options.progress {
+            progress.on_discovered();
+        }
+        if traversal.deadline_passed() {
+            let e = Error::DeadlineExceeded {
+                path: prefix,
+                depth,
+                parents,
+            };
+            traversal.report(e).await?;
+            continue;
+        }
+        // Spawned tasks don't inherit the current span, so create the span here and
+        // attach it to the task, so it's the child of the span of the parent prefix.
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!(
+            "list_prefix",
+            prefix = %prefix,
+            depth,
+            objects = tracing::field::Empty,
+            common_prefixes = tracing::field::Empty,
+            error = tracing::field::Empty,
+        );
+        let task = visit(traversal.clone(), prefix, depth, parents);
+        #[cfg(feature = "tracing")]
+        let task = tracing::Instrument::instrument(task, span);
+        set.spawn(task);
+    }
+
+    // Propagate errors:
+    while let Some(handle) = set.join_next().await {
+        let result = match handle {
+            Ok(result) => result,
+            Err(join_error) => Err(Error::from(join_error)),
+        };
+        if let Err(e) = result {
+            traversal.report(e).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Lists `prefix` and then descends into it. The empty path is the root of the store.
+async fn visit(
+    traversal: Arc<Traversal>,
+    prefix: Path,
+    depth: usize,
+    parents: Vec<Path>,
+) -> Result<()> {
+    let prefix_to_list = Some(&prefix).filter(|prefix| !prefix.as_ref().is_empty());
+    let list_result = match traversal.list(prefix_to_list, depth, &parents).await {
+        Ok(list_result) => list_result,
+        Err(e) => {
+            #[cfg(feature = "tracing")]
+            tracing::Span::current().record("error", tracing::field::display(&e));
+            return traversal.report(e).await;
+        }
+    };
+    #[cfg(feature = "tracing")]
+    tracing::Span::current()
+        .record("objects", list_result.objects.len())
+        .record("common_prefixes", list_result.common_prefixes.len());
+    traversal.stats.on_listed(depth);
+    if let Some(progress) = &traversal.options.progress {
+        progress.on_listed(depth, &list_result);
+    }
+
+    // Recursive call to next_level:
+    next_level(traversal, prefix, parents, list_result, depth).await
+}
+
+/// `parents` are the ancestors of `prefix`, outermost first, excluding the root of the store.
+fn next_level(
+    traversal: Arc<Traversal>,
+    prefix: Path,
+    parents: Vec<Path>,
+    list_result: ListResult,
+    depth_of_list_result: usize,
+) -> Pin<Box<dyn Future<Output = Result<()>> + Send>> {
+    // See here for why we're using `Box::pin`:
+    // https://stackoverflow.com/a/67030773
+    Box::pin(async move {
+        let mut is_leaf = depth_of_list_result >= *traversal.depths.end();
+        let mut common_prefixes = vec![];
+        if let Some(visitor) = &traversal.visitor {
+            visitor
+                .on_objects(&prefix, depth_of_list_result, &list_result.objects)
+                .await;
+            is_leaf = is_leaf
+                || !visitor
+                    .should_descend(&prefix, depth_of_list_result, &list_result)
+                    .await;
+            if !is_leaf {
+                for common_prefix in &list_result.common_prefixes {
+                    if visitor
+                        .on_prefix(common_prefix, depth_of_list_result + 1)
+                        .await
+                    {
+                        common_prefixes.push(common_prefix.clone());
+                    }
+                }
+            }
+        } else if !is_leaf {
+            common_prefixes = list_result.common_prefixes.clone();
+        }
+
+        let mut child_parents = parents;
+        if !is_leaf && !prefix.as_ref().is_empty() {
+            child_parents.push(prefix.clone());
+        }
+
+        // Send this listing *before* spawning the children, so consumers always see a
+        // parent before its children.
+        if traversal.depths.contains(&depth_of_list_result) {
+            let prefix_listing = PrefixListing {
+                prefix,
+                depth: depth_of_list_result,
+                list_result,
+            };
+            // If the send fails then the `ListStream` has been dropped, so there's
+            // nobody left to tell.
+            if traversal.tx.send(Ok(prefix_listing)).await.is_err() {
+                return Ok(());
+            }
+        }
+
+        // Base case:
+        if is_leaf {
+            return Ok(());
+        }
+
+        let depth = depth_of_list_result + 1;
+        let frontier = common_prefixes
+            .into_iter()
+            .map(|common_prefix| (common_prefix, depth, child_parents.clone()));
+        visit_all(&traversal, frontier).await
+    })
+}
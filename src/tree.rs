@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+
+use object_store::{path::Path, ObjectMeta};
+
+use crate::PrefixListing;
+
+/// A listing which keeps track of which objects belong to which prefix, returned by
+/// [`list_tree`](crate::list_tree).
+///
+/// Each `ListTree` is one prefix, with the objects directly beneath it, and a child
+/// `ListTree` for each of its common prefixes (down to the requested depth).
+#[derive(Debug)]
+pub struct ListTree {
+    /// The prefix that was listed. This is the empty path for the root of the store.
+    pub prefix: Path,
+    /// The depth of `prefix`, relative to the prefix that the traversal started from.
+    pub depth: usize,
+    /// The objects directly beneath `prefix`, sorted by path.
+    pub objects: Vec<ObjectMeta>,
+    /// A subtree for each common prefix directly beneath `prefix`, sorted by prefix.
+    /// Empty at the requested depth, because the traversal doesn't list any deeper.
+    pub children: Vec<ListTree>,
+    /// The common prefixes directly beneath `prefix` which weren't listed because they're
+    /// below the requested depth, sorted by path. Empty above the requested depth.
+    pub leaf_prefixes: Vec<Path>,
+}
+
+impl ListTree {
+    /// Builds a tree from the listings of a traversal which emits every depth from `0`
+    /// to `max_depth`. `root` is the prefix that the traversal started from.
+    pub(crate) fn from_listings(
+        root: &Path,
+        max_depth: usize,
+        mut listings: HashMap<Path, PrefixListing>,
+    ) -> Self {
+        fn build(
+            prefix: &Path,
+            max_depth: usize,
+            listings: &mut HashMap<Path, PrefixListing>,
+        ) -> Option<ListTree> {
+            let PrefixListing {
+                prefix,
+                depth,
+                mut list_result,
+            } = listings.remove(prefix)?;
+            list_result
+                .objects
+                .sort_unstable_by(|a, b| a.location.cmp(&b.location));
+            list_result.common_prefixes.sort_unstable();
+            let (children, leaf_prefixes) = if depth < max_depth {
+                let children = list_result
+                    .common_prefixes
+                    .iter()
+                    .filter_map(|common_prefix| build(common_prefix, max_depth, listings))
+                    .collect();
+                (children, vec![])
+            } else {
+                (vec![], list_result.common_prefixes)
+            };
+            Some(ListTree {
+                prefix,
+                depth,
+                objects: list_result.objects,
+                children,
+                leaf_prefixes,
+            })
+        }
+
+        build(root, max_depth, &mut listings).expect("the root is always listed")
+    }
+
+    /// Iterates over every node, visiting each node before its children.
+    pub fn pre_order(&self) -> impl Iterator<Item = &ListTree> {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            stack.extend(node.children.iter().rev());
+            Some(node)
+        })
+    }
+
+    /// Iterates over every node, visiting each node after its children. This is the order
+    /// in which to aggregate values up the tree.
+    pub fn post_order(&self) -> impl Iterator<Item = &ListTree> {
+        // Each item is a node, and the number of its children which have been visited.
+        let mut stack = vec![(self, 0)];
+        std::iter::from_fn(move || loop {
+            let (node, n_visited) = stack.last_mut()?;
+            let node = *node;
+            match node.children.get(*n_visited) {
+                Some(child) => {
+                    *n_visited += 1;
+                    stack.push((child, 0));
+                }
+                None => {
+                    stack.pop();
+                    return Some(node);
+                }
+            }
+        })
+    }
+
+    /// Iterates over the levels of the tree, starting with `self`. Each item holds every
+    /// node at one depth, sorted by prefix.
+    pub fn levels(&self) -> impl Iterator<Item = Vec<&ListTree>> {
+        let mut level = vec![self];
+        std::iter::from_fn(move || {
+            if level.is_empty() {
+                return None;
+            }
+            let next_level = level.iter().flat_map(|node| node.children.iter()).collect();
+            Some(std::mem::replace(&mut level, next_level))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{list_tree, test_utils::create_in_memory_store};
+
+    use super::*;
+
+    fn prefixes<'a>(nodes: impl IntoIterator<Item = &'a ListTree>) -> Vec<&'a str> {
+        nodes.into_iter().map(|node| node.prefix.as_ref()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_list_tree() -> object_store::Result<()> {
+        let store = Arc::new(create_in_memory_store().await?);
+        let tree = list_tree(store, None, 2).await?;
+
+        assert_eq!(tree.prefix, Path::default());
+        assert_eq!(tree.objects[0].location, Path::from("a.txt"));
+        let foo = &tree.children[0];
+        assert_eq!(foo.prefix, Path::from("foo"));
+        assert_eq!(foo.objects[0].location, Path::from("foo/b.txt"));
+        let baz = &foo.children[1];
+        assert_eq!(baz.depth, 2);
+        assert_eq!(baz.objects[0].location, Path::from("foo/baz/e.txt"));
+        assert!(baz.children.is_empty());
+        assert_eq!(baz.leaf_prefixes, vec![Path::from("foo/baz/bleh")]);
+
+        assert_eq!(
+            prefixes(tree.pre_order()),
+            vec!["", "foo", "foo/bar", "foo/baz"]
+        );
+        assert_eq!(
+            prefixes(tree.post_order()),
+            vec!["foo/bar", "foo/baz", "foo", ""]
+        );
+        let levels: Vec<Vec<&str>> = tree.levels().map(prefixes).collect();
+        assert_eq!(
+            levels,
+            vec![vec![""], vec!["foo"], vec!["foo/bar", "foo/baz"]]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_list_tree_depth_0() -> object_store::Result<()> {
+        let store = Arc::new(create_in_memory_store().await?);
+        let tree = list_tree(store, Some(&Path::from("foo")), 0).await?;
+        assert_eq!(tree.objects.len(), 1);
+        assert!(tree.children.is_empty());
+        assert_eq!(
+            tree.leaf_prefixes,
+            vec![Path::from("foo/bar"), Path::from("foo/baz")]
+        );
+        assert_eq!(tree.post_order().count(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+use async_trait::async_trait;
+use object_store::{path::Path, ListResult, ObjectMeta};
+
+/// Controls which prefixes the traversal descends into, and observes the results.
+///
+/// Pass a `ListVisitor` to [`list_with_visitor`](crate::list_with_visitor). For every
+/// prefix that is listed, the traversal calls:
+/// 1. [`on_objects`](Self::on_objects) with the objects directly beneath the prefix.
+/// 2. [`should_descend`](Self::should_descend), to decide whether to list *any* of the
+///    prefix's common prefixes.
+/// 3. [`on_prefix`](Self::on_prefix) for each common prefix, to decide whether to list
+///    that common prefix.
+///
+/// All the methods have default implementations which visit everything, so you only need
+/// to implement the hooks you care about. The hooks are called concurrently from many
+/// Tokio tasks, so any state must be behind a `Mutex` or similar.
+///
+/// ```
+/// use async_trait::async_trait;
+/// use list_with_depth::ListVisitor;
+/// use object_store::{path::Path, ListResult};
+///
+/// /// Skips `_tmp` prefixes, and stops descending into Zarr v3 arrays and groups.
+/// struct ZarrVisitor;
+///
+/// #[async_trait]
+/// impl ListVisitor for ZarrVisitor {
+///     async fn on_prefix(&self, prefix: &Path, _depth: usize) -> bool {
+///         prefix.filename() != Some("_tmp")
+///     }
+///
+///     async fn should_descend(&self, _prefix: &Path, _depth: usize, list_result: &ListResult) -> bool {
+///         !list_result
+///             .objects
+///             .iter()
+///             .any(|object_meta| object_meta.location.filename() == Some("zarr.json"))
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait ListVisitor: Send + Sync + 'static {
+    /// Called for each common prefix, before it is listed. `depth` is the depth of
+    /// `prefix`. Return `false` to skip `prefix` and everything beneath it.
+    async fn on_prefix(&self, _prefix: &Path, _depth: usize) -> bool {
+        true
+    }
+
+    /// Called after `prefix` has been listed. Return `false` to stop the traversal from
+    /// descending into any of `list_result.common_prefixes`.
+    async fn should_descend(
+        &self,
+        _prefix: &Path,
+        _depth: usize,
+        _list_result: &ListResult,
+    ) -> bool {
+        true
+    }
+
+    /// Called with the objects directly beneath `prefix`, after `prefix` has been listed.
+    async fn on_objects(&self, _prefix: &Path, _depth: usize, _objects: &[ObjectMeta]) {}
+}